@@ -7,25 +7,20 @@ use reqwest::Url;
 use serde::{Deserialize, Serialize};
 
 use ag_ui_client::agent::{AgentError, AgentStateMutation, RunAgentParams};
-use ag_ui_client::core::AgentState;
 use ag_ui_client::core::event::{StateDeltaEvent, StateSnapshotEvent};
 use ag_ui_client::core::types::Message;
+use ag_ui_client::core::{AgentState, PatchOperation};
 use ag_ui_client::subscriber::{AgentSubscriber, AgentSubscriberParams};
 use ag_ui_client::{Agent, HttpAgent};
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum StepStatus {
+    #[default]
     Pending,
     Completed,
 }
 
-impl Default for StepStatus {
-    fn default() -> Self {
-        StepStatus::Pending
-    }
-}
-
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Step {
     pub description: String,
@@ -50,6 +45,7 @@ pub struct Plan {
 
 impl AgentState for Plan {}
 
+#[derive(Default)]
 pub struct GenerativeUiSubscriber;
 
 impl GenerativeUiSubscriber {
@@ -84,31 +80,26 @@ impl AgentSubscriber<Plan, ()> for GenerativeUiSubscriber {
         _params: AgentSubscriberParams<'async_trait, Plan, ()>,
     ) -> Result<AgentStateMutation<Plan>, AgentError> {
         info!("State delta received:");
-        for patch in &event.delta {
-            match patch.get("op").and_then(|v| v.as_str()) {
-                Some("replace") => {
-                    if let (Some(path), Some(value)) = (
-                        patch.get("path").and_then(|v| v.as_str()),
-                        patch.get("value"),
-                    ) {
-                        if path.contains("/status") {
-                            let status = value.as_str().unwrap_or("unknown");
-                            let status_icon = match status {
-                                "completed" => "[X]",
-                                "pending" => "[ ]",
-                                _ => "[?]",
-                            };
-                            info!("   {} Step status updated to: {}", status_icon, status);
-                        } else if path.contains("/description") {
-                            info!(
-                                "   Step description updated to: {}",
-                                value.as_str().unwrap_or("unknown")
-                            );
-                        }
+        for patch in event.delta.iter() {
+            match patch {
+                PatchOperation::Replace(op) => {
+                    let path = op.path.as_str();
+                    if path.contains("/status") {
+                        let status = op.value.as_str().unwrap_or("unknown");
+                        let status_icon = match status {
+                            "completed" => "[X]",
+                            "pending" => "[ ]",
+                            _ => "[?]",
+                        };
+                        info!("   {} Step status updated to: {}", status_icon, status);
+                    } else if path.contains("/description") {
+                        info!(
+                            "   Step description updated to: {}",
+                            op.value.as_str().unwrap_or("unknown")
+                        );
                     }
                 }
-                Some(op) => info!("   Operation: {}", op),
-                None => info!("   Unknown operation"),
+                op => info!("   Operation: {:?}", op),
             }
         }
         Ok(AgentStateMutation::default())
@@ -1,14 +1,18 @@
 use futures::stream::StreamExt;
+use serde::de::DeserializeOwned;
 use std::collections::HashSet;
 
 use crate::core::JsonValue;
+use crate::core::event::Event;
 use crate::core::types::{
-    AgentId, Context, Message, MessageId, RunAgentInput, RunId, ThreadId, Tool,
+    AgentId, Context, DRY_RUN_PROP, Message, MessageId, REGENERATE_PROP, Regeneration,
+    RunAgentInput, RunId, ThreadId, Tool, ToolCall, ToolCallId,
 };
-use crate::core::{AgentState, FwdProps};
+use crate::core::{AgentState, FwdProps, Patch, StatePatcher};
 use crate::event_handler::EventHandler;
 use crate::stream::EventStream;
 use crate::subscriber::IntoSubscribers;
+use crate::tool_calls::{ToolCallTracker, TrackedToolCall};
 
 /// Configuration for an Agent.
 #[derive(Debug, Clone)]
@@ -37,15 +41,21 @@ where
     }
 }
 
+/// Checks that the result of a run has the expected type, see [`RunAgentParams::expect_result`].
+pub type ResultValidator = fn(&JsonValue) -> Result<(), AgentError>;
+
 /// Parameters for running an agent.
 #[derive(Debug, Clone, Default)]
 pub struct RunAgentParams<StateT: AgentState = JsonValue, FwdPropsT: FwdProps = JsonValue> {
+    pub thread_id: Option<ThreadId>,
     pub run_id: Option<RunId>,
     pub tools: Vec<Tool>,
     pub context: Vec<Context>,
     pub forwarded_props: FwdPropsT,
     pub messages: Vec<Message>,
     pub state: StateT,
+    /// Validates the result when `RUN_FINISHED` is received
+    pub result_validator: Option<ResultValidator>,
 }
 
 impl<StateT, FwdPropsT> RunAgentParams<StateT, FwdPropsT>
@@ -59,15 +69,21 @@ where
     /// If you do not need this level of customization, use [RunAgentParams::new].
     pub fn new_typed() -> Self {
         Self {
+            thread_id: None,
             run_id: None,
             tools: Vec::new(),
             context: Vec::new(),
             forwarded_props: FwdPropsT::default(),
             messages: Vec::new(),
             state: StateT::default(),
+            result_validator: None,
         }
     }
 
+    pub fn with_thread_id(mut self, thread_id: ThreadId) -> Self {
+        self.thread_id = Some(thread_id);
+        self
+    }
     pub fn with_run_id(mut self, run_id: RunId) -> Self {
         self.run_id = Some(run_id);
         self
@@ -88,6 +104,12 @@ where
         self.state = state;
         self
     }
+    /// Declares the type of the run result. A result that does not deserialize into `T` fails
+    /// the run with [`AgentError::InvalidResult`] as soon as `RUN_FINISHED` is received.
+    pub fn expect_result<T: DeserializeOwned>(mut self) -> Self {
+        self.result_validator = Some(|result| parse_result::<T>(result).map(|_| ()));
+        self
+    }
     pub fn add_message(mut self, msg: Message) -> Self {
         self.messages.push(msg);
         self
@@ -97,11 +119,67 @@ where
             id: MessageId::random(),
             content: content.into(),
             name: None,
+            metadata: None,
+        });
+        self
+    }
+    /// Adds the result of a tool call requested by the agent, to be sent in the next run.
+    pub fn with_tool_result(
+        mut self,
+        tool_call_id: ToolCallId,
+        content: impl Into<String>,
+    ) -> Self {
+        self.messages.push(Message::Tool {
+            id: MessageId::random(),
+            content: content.into(),
+            tool_call_id,
+            error: None,
+            metadata: None,
         });
         self
     }
 }
 
+impl<StateT: AgentState> RunAgentParams<StateT, JsonValue> {
+    /// Asks the agent to plan without executing side-effecting tools, by setting the
+    /// [`DRY_RUN_PROP`] forwarded prop.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        if !self.forwarded_props.is_object() {
+            self.forwarded_props = JsonValue::Object(Default::default());
+        }
+        self.forwarded_props[DRY_RUN_PROP] = JsonValue::Bool(dry_run);
+        self
+    }
+
+    /// Discards the messages following `message_id`, typically the last user message, and sets
+    /// the [`REGENERATE_PROP`] forwarded prop so that the agent responds to it again.
+    ///
+    /// Fails if the messages do not contain `message_id`.
+    pub fn regenerate_after(mut self, message_id: &MessageId) -> Result<Self, AgentError> {
+        let position = self
+            .messages
+            .iter()
+            .position(|message| message.id() == message_id)
+            .ok_or_else(|| {
+                AgentError::config(format!("No message {message_id} to regenerate after"))
+            })?;
+        let regeneration = Regeneration {
+            after_message_id: message_id.clone(),
+            replaced_message_ids: self
+                .messages
+                .drain(position + 1..)
+                .map(|message| message.id().clone())
+                .collect(),
+        };
+
+        if !self.forwarded_props.is_object() {
+            self.forwarded_props = JsonValue::Object(Default::default());
+        }
+        self.forwarded_props[REGENERATE_PROP] = serde_json::to_value(regeneration)?;
+        Ok(self)
+    }
+}
+
 impl RunAgentParams<JsonValue, JsonValue> {
     /// Construct an empty parameter object with JSON Values for state and forwarded props.
     ///
@@ -113,25 +191,218 @@ impl RunAgentParams<JsonValue, JsonValue> {
 
 #[derive(Debug, Clone)]
 pub struct RunAgentResult<StateT: AgentState> {
+    /// The thread of the run, generated unless given in [`RunAgentParams::thread_id`]
+    pub thread_id: ThreadId,
     pub result: JsonValue,
+    /// All messages of the conversation when the run finished, including the changes the run
+    /// made to the messages it was given
+    pub messages: Vec<Message>,
     pub new_messages: Vec<Message>,
     pub new_state: StateT,
+    /// The tool calls of the conversation, including those of earlier runs
+    pub tool_calls: ToolCallTracker,
+    /// The tool calls still waiting for a result when the run finished
+    pub pending_tool_calls: Vec<TrackedToolCall>,
+}
+
+impl<StateT: AgentState> RunAgentResult<StateT> {
+    /// Whether the run ended waiting for the results of client-side tool calls, as in
+    /// human-in-the-loop flows. This is a regular outcome rather than an error: submit the
+    /// results with [`Agent::submit_tool_result`] to continue the conversation.
+    pub fn is_awaiting_tool_results(&self) -> bool {
+        !self.pending_tool_calls.is_empty()
+    }
+
+    /// Deserializes the result of the run into `T`.
+    pub fn result_as<T: DeserializeOwned>(&self) -> Result<T, AgentError> {
+        parse_result(&self.result)
+    }
+}
+
+fn parse_result<T: DeserializeOwned>(result: &JsonValue) -> Result<T, AgentError> {
+    T::deserialize(result).map_err(|source| AgentError::InvalidResult {
+        type_name: std::any::type_name::<T>(),
+        source,
+    })
 }
 
 pub type AgentRunState<StateT, FwdPropsT> = RunAgentInput<StateT, FwdPropsT>;
 
+/// A change to the messages or state of a run.
+///
+/// Changes describe *what* changed, as opposed to the snapshot form of [`AgentStateMutation`]
+/// which carries the entire message history. Subscribers receive them through
+/// [`AgentSubscriber::on_changes`](crate::subscriber::AgentSubscriber::on_changes).
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentChange<StateT = JsonValue> {
+    /// A message was appended to the history
+    Append(Message),
+    /// Text was appended to the content of a message
+    UpdateContent { id: MessageId, delta: String },
+    /// A tool call was added to an assistant message
+    AppendToolCall {
+        message_id: MessageId,
+        tool_call: ToolCall,
+    },
+    /// Text was appended to the arguments of a tool call
+    UpdateToolCallArgs {
+        tool_call_id: ToolCallId,
+        delta: String,
+    },
+    /// The content of a message was replaced by a revision, see
+    /// [`MessageRevision`](crate::core::extensions::MessageRevision)
+    ReviseContent { id: MessageId, content: String },
+    /// A message was inserted at a position of the history, see
+    /// [`MessagesDelta`](crate::core::extensions::MessagesDelta)
+    Insert { index: usize, message: Message },
+    /// The message at a position of the history was removed
+    Remove { index: usize },
+    /// The message at a position of the history was replaced
+    Replace { index: usize, message: Message },
+    /// The messages were replaced outright, such as by a subscriber returning new messages
+    SetMessages(Vec<Message>),
+    /// The state was replaced
+    SetState(StateT),
+    /// A JSON Patch was applied to the state, such as by a `STATE_DELTA` event. Carries the
+    /// patch rather than the patched state, so that a delta does not copy the whole state.
+    PatchState(Patch),
+}
+
+impl<StateT: AgentState> AgentChange<StateT> {
+    /// Applies the change to the given messages and state, patching the state with `patcher`,
+    /// which must be kept for the series of changes applied to this state.
+    ///
+    /// Changes referring to a message or tool call that does not exist, and patches that do not
+    /// apply to the state, are ignored.
+    pub fn apply(
+        &self,
+        messages: &mut Vec<Message>,
+        state: &mut StateT,
+        patcher: &mut StatePatcher,
+    ) {
+        match self {
+            AgentChange::SetState(new_state) => {
+                *state = new_state.clone();
+                patcher.reset();
+            }
+            AgentChange::PatchState(patch) => {
+                // The state is left as it was on failure
+                let _ = patcher.apply(state, patch);
+            }
+            change => change.apply_to_messages(messages),
+        }
+    }
+}
+
+impl<StateT: Clone> AgentChange<StateT> {
+    /// Applies a change to the messages, ignoring changes to the state
+    pub(crate) fn apply_to_messages(&self, messages: &mut Vec<Message>) {
+        match self {
+            AgentChange::Append(message) => messages.push(message.clone()),
+            AgentChange::UpdateContent { id, delta } => {
+                if let Some(content) = messages
+                    .iter_mut()
+                    .rfind(|m| m.id() == id)
+                    .and_then(|m| m.content_mut())
+                {
+                    content.push_str(delta);
+                }
+            }
+            AgentChange::AppendToolCall {
+                message_id,
+                tool_call,
+            } => {
+                if let Some(tool_calls) = messages
+                    .iter_mut()
+                    .rfind(|m| m.id() == message_id)
+                    .and_then(|m| m.tool_calls_mut())
+                {
+                    tool_calls.push(tool_call.clone());
+                }
+            }
+            AgentChange::UpdateToolCallArgs {
+                tool_call_id,
+                delta,
+            } => {
+                if let Some(tool_call) = messages
+                    .iter_mut()
+                    .rev()
+                    .filter_map(|m| m.tool_calls_mut())
+                    .flat_map(|tc| tc.iter_mut())
+                    .find(|tc| tc.id == *tool_call_id)
+                {
+                    tool_call.function.arguments.push_str(delta);
+                }
+            }
+            AgentChange::ReviseContent { id, content } => {
+                if let Some(current) = messages
+                    .iter_mut()
+                    .rfind(|m| m.id() == id)
+                    .and_then(|m| m.content_mut())
+                {
+                    *current = content.clone();
+                }
+            }
+            AgentChange::Insert { index, message } => {
+                if *index <= messages.len() {
+                    messages.insert(*index, message.clone());
+                }
+            }
+            AgentChange::Remove { index } => {
+                if *index < messages.len() {
+                    messages.remove(*index);
+                }
+            }
+            AgentChange::Replace { index, message } => {
+                if let Some(current) = messages.get_mut(*index) {
+                    *current = message.clone();
+                }
+            }
+            AgentChange::SetMessages(new_messages) => *messages = new_messages.clone(),
+            AgentChange::SetState(_) | AgentChange::PatchState(_) => {}
+        }
+    }
+
+    /// Whether the change affects the messages (as opposed to the state)
+    pub fn is_message_change(&self) -> bool {
+        !matches!(self, AgentChange::SetState(_) | AgentChange::PatchState(_))
+    }
+}
+
+/// A mutation of the run state returned by subscribers.
+///
+/// `messages` and `state` replace the current values wholesale (the snapshot form), while
+/// `changes` are applied incrementally on top of them. Both may be combined.
 #[derive(Debug, Clone)]
 pub struct AgentStateMutation<StateT = JsonValue> {
     pub messages: Option<Vec<Message>>,
     pub state: Option<StateT>,
+    pub changes: Vec<AgentChange<StateT>>,
     pub stop_propagation: bool,
 }
 
+impl<StateT> AgentStateMutation<StateT> {
+    /// Creates a mutation from incremental changes
+    pub fn from_changes(changes: Vec<AgentChange<StateT>>) -> Self {
+        Self {
+            changes,
+            ..Self::default()
+        }
+    }
+
+    /// Adds an incremental change to the mutation
+    pub fn with_change(mut self, change: AgentChange<StateT>) -> Self {
+        self.changes.push(change);
+        self
+    }
+}
+
 impl<StateT> Default for AgentStateMutation<StateT> {
     fn default() -> Self {
         Self {
             messages: None,
             state: None,
+            changes: Vec::new(),
             stop_propagation: false,
         }
     }
@@ -182,6 +453,13 @@ where
     /// # }
     /// ```
     ///
+    /// # Ordering
+    /// Events for different messages and tool calls may be interleaved. Content and argument
+    /// deltas are applied to the message or tool call named by their id, which must have been
+    /// started earlier in the run (or be part of the input messages); otherwise the run fails with
+    /// [`AgentError::MisattributedDelta`]. A tool call whose parent message is absent starts a new
+    /// assistant message.
+    ///
     /// # Notes
     /// Currently the subscriber pattern is the only way to subscriber to an Agent run's lifecycle.
     async fn run_agent(
@@ -190,7 +468,7 @@ where
         subscribers: impl IntoSubscribers<StateT, FwdPropsT>,
     ) -> Result<RunAgentResult<StateT>, AgentError> {
         let input = RunAgentInput {
-            thread_id: ThreadId::random(),
+            thread_id: params.thread_id.clone().unwrap_or_else(ThreadId::random),
             run_id: params.run_id.clone().unwrap_or_else(RunId::random),
             state: params.state.clone(),
             messages: params.messages.clone(),
@@ -217,6 +495,14 @@ where
                 Ok(event) => {
                     let mutation = event_handler.handle_event(&event).await?;
                     event_handler.apply_mutation(mutation).await?;
+
+                    if let Event::RunFinished(_) = event
+                        && let Some(validate) = params.result_validator
+                        && let Err(e) = validate(&event_handler.result)
+                    {
+                        event_handler.on_error(&e).await?;
+                        return Err(e);
+                    }
                 }
                 Err(e) => {
                     event_handler.on_error(&e).await?;
@@ -237,13 +523,282 @@ where
             .collect();
 
         Ok(RunAgentResult {
+            thread_id: input.thread_id.clone(),
             result: event_handler.result,
+            messages: event_handler.messages,
             new_messages,
             new_state: event_handler.state,
+            pending_tool_calls: event_handler.tool_calls.pending(),
+            tool_calls: event_handler.tool_calls,
         })
     }
 
+    /// Submits the result of a pending tool call of a previous run and starts the run continuing
+    /// the conversation on the same thread.
+    ///
+    /// `params` are the parameters of the previous run and `previous` its result: the next run
+    /// sends the messages and state the previous run ended with, followed by a tool message with
+    /// `content`, and takes everything else from `params`. Once the run succeeds, the tool call is
+    /// resolved in the tracker of `previous`. Fails with a configuration error if the tool call
+    /// is unknown or was already resolved.
+    async fn submit_tool_result(
+        &self,
+        params: &RunAgentParams<StateT, FwdPropsT>,
+        previous: &RunAgentResult<StateT>,
+        tool_call_id: &ToolCallId,
+        content: impl Into<String> + Send,
+        subscribers: impl IntoSubscribers<StateT, FwdPropsT>,
+    ) -> Result<RunAgentResult<StateT>, AgentError> {
+        match previous.tool_calls.get(tool_call_id) {
+            Some(tool_call) if tool_call.is_pending() => {}
+            Some(_) => {
+                return Err(AgentError::config(format!(
+                    "Tool call {} was already resolved",
+                    &**tool_call_id
+                )));
+            }
+            None => {
+                return Err(AgentError::config(format!(
+                    "Unknown tool call {}",
+                    &**tool_call_id
+                )));
+            }
+        }
+
+        let content = content.into();
+        let mut params = params
+            .clone()
+            .with_thread_id(previous.thread_id.clone())
+            .with_state(previous.new_state.clone());
+        params.run_id = None;
+        params.messages = previous.messages.clone();
+        let params = params.with_tool_result(tool_call_id.clone(), content.clone());
+
+        let result = self.run_agent(&params, subscribers).await?;
+        previous.tool_calls.resolve(tool_call_id, &content);
+        Ok(result)
+    }
+
     fn agent_id(&self) -> Option<&AgentId> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::event::{
+        RunFinishedEvent, ToolCallArgsEvent, ToolCallEndEvent, ToolCallStartEvent,
+    };
+    use crate::core::extensions::{CustomEventExtension, MessagesDelta};
+    use crate::event_handler::testing::input_from;
+    use serde::Deserialize;
+    use std::sync::Mutex;
+
+    /// Records the input of each run and emits a fixed sequence of events
+    #[derive(Default)]
+    struct RecordingAgent {
+        runs: Mutex<Vec<RunAgentInput>>,
+        events: Vec<Event>,
+    }
+
+    #[async_trait::async_trait]
+    impl Agent for RecordingAgent {
+        async fn run(
+            &self,
+            input: &RunAgentInput,
+        ) -> Result<EventStream<'async_trait, JsonValue>, AgentError> {
+            self.runs.lock().unwrap().push(input.clone());
+            Ok(futures::stream::iter(self.events.clone().into_iter().map(Ok)).boxed())
+        }
+    }
+
+    fn tool_call_events(tool_call_id: &ToolCallId, name: &str) -> Vec<Event> {
+        vec![
+            Event::ToolCallStart(ToolCallStartEvent::new(tool_call_id.clone(), name)),
+            Event::ToolCallArgs(ToolCallArgsEvent::new(tool_call_id.clone(), "{}")),
+            Event::ToolCallEnd(ToolCallEndEvent::new(tool_call_id.clone())),
+            Event::RunFinished(RunFinishedEvent::new(ThreadId::random(), RunId::random())),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_submit_tool_result() {
+        let tool_call_id = ToolCallId::random();
+        let params = RunAgentParams::new().user("Show me the items");
+        let requesting = RecordingAgent {
+            events: tool_call_events(&tool_call_id, "render_ItemsList"),
+            ..RecordingAgent::default()
+        };
+        let previous = requesting.run_agent(&params, ()).await.unwrap();
+        assert!(previous.is_awaiting_tool_results());
+
+        let agent = RecordingAgent::default();
+        let result = agent
+            .submit_tool_result(&params, &previous, &tool_call_id, "approved", ())
+            .await
+            .unwrap();
+        assert!(result.tool_calls.pending().is_empty());
+        // The tool call is resolved in the tracker of the previous run
+        assert!(previous.tool_calls.pending().is_empty());
+
+        // The continuation runs on the same thread, with the messages of the previous run
+        let first = requesting.runs.lock().unwrap().remove(0);
+        let continuation = agent.runs.lock().unwrap().remove(0);
+        assert_eq!(continuation.thread_id, first.thread_id);
+        assert_eq!(result.thread_id, previous.thread_id);
+        assert_ne!(continuation.run_id, first.run_id);
+        let messages = continuation.messages;
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1], previous.new_messages[0]);
+        assert!(
+            matches!(&messages[2], Message::Tool { tool_call_id: id, content, .. } if *id == tool_call_id && content == "approved")
+        );
+
+        let err = agent
+            .submit_tool_result(&params, &previous, &tool_call_id, "again", ())
+            .await
+            .unwrap_err();
+        assert!(err.is_user_input());
+        let err = agent
+            .submit_tool_result(&params, &previous, &ToolCallId::random(), "unknown", ())
+            .await
+            .unwrap_err();
+        assert!(err.is_user_input());
+    }
+
+    #[tokio::test]
+    async fn test_submit_tool_result_continues_from_revised_messages() {
+        let tool_call_id = ToolCallId::random();
+        let params = RunAgentParams::new().user("Show me the itmes");
+        // The previous run corrects the input message before calling the tool
+        let delta = MessagesDelta::new(
+            serde_json::from_value(serde_json::json!([
+                {"op": "replace", "path": "/0/content", "value": "Show me the items"},
+            ]))
+            .unwrap(),
+        );
+        let mut events = vec![delta.into_event().unwrap()];
+        events.extend(tool_call_events(&tool_call_id, "render_ItemsList"));
+        let requesting = RecordingAgent {
+            events,
+            ..RecordingAgent::default()
+        };
+        let previous = requesting.run_agent(&params, ()).await.unwrap();
+
+        let agent = RecordingAgent::default();
+        agent
+            .submit_tool_result(&params, &previous, &tool_call_id, "approved", ())
+            .await
+            .unwrap();
+
+        let messages = agent.runs.lock().unwrap().remove(0).messages;
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].id(), params.messages[0].id());
+        assert_eq!(messages[0].content(), Some("Show me the items"));
+        assert_eq!(messages[..2], previous.messages[..]);
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Forecast {
+        city: String,
+        celsius: f64,
+    }
+
+    fn finishing_agent(result: JsonValue) -> RecordingAgent {
+        RecordingAgent {
+            events: vec![Event::RunFinished(
+                RunFinishedEvent::new(ThreadId::random(), RunId::random())
+                    .with_result(&result)
+                    .unwrap(),
+            )],
+            ..RecordingAgent::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_typed_result() {
+        let agent = finishing_agent(serde_json::json!({"city": "Oslo", "celsius": -3.5}));
+        let params = RunAgentParams::new().expect_result::<Forecast>();
+        let result = agent.run_agent(&params, ()).await.unwrap();
+        assert_eq!(
+            result.result_as::<Forecast>().unwrap(),
+            Forecast {
+                city: "Oslo".to_string(),
+                celsius: -3.5
+            }
+        );
+        assert!(matches!(
+            result.result_as::<Vec<String>>(),
+            Err(AgentError::InvalidResult { .. })
+        ));
+
+        let agent = finishing_agent(serde_json::json!({"city": "Oslo"}));
+        let err = agent.run_agent(&params, ()).await.unwrap_err();
+        assert!(
+            matches!(err, AgentError::InvalidResult { type_name, .. } if type_name.ends_with("Forecast"))
+        );
+    }
+
+    #[test]
+    fn test_dry_run_prop() {
+        let params = RunAgentParams::new().with_dry_run(true);
+        let input = input_from(params);
+        assert!(input.is_dry_run());
+
+        let params = RunAgentParams::new()
+            .with_forwarded_props(serde_json::json!({"mode": "fast"}))
+            .with_dry_run(false);
+        assert_eq!(
+            params.forwarded_props,
+            serde_json::json!({"mode": "fast", "dryRun": false})
+        );
+    }
+
+    #[test]
+    fn test_regenerate_after() {
+        let question = Message::new_user("Weather in Paris?");
+        let answer = Message::new_assistant("Rainy.");
+        let mut history = vec![question.clone(), answer.clone()];
+        let params = RunAgentParams::new()
+            .add_message(question.clone())
+            .add_message(answer.clone())
+            .regenerate_after(question.id())
+            .unwrap();
+        assert_eq!(params.messages, vec![question.clone()]);
+
+        let input = input_from(params);
+        assert!(input.is_regeneration());
+        let regeneration = input.regeneration().unwrap();
+        assert_eq!(regeneration.after_message_id, *question.id());
+        assert_eq!(regeneration.replaced_message_ids, vec![answer.id().clone()]);
+
+        // A stored history drops the replaced answer instead of keeping it before the new one
+        regeneration.truncate(&mut history);
+        assert_eq!(history, input.messages);
+
+        let err = RunAgentParams::new()
+            .regenerate_after(&MessageId::random())
+            .unwrap_err();
+        assert!(err.is_user_input());
+    }
+
+    #[tokio::test]
+    async fn test_run_finished_with_pending_tool_calls() {
+        let tool_call_id = ToolCallId::random();
+        let agent = RecordingAgent {
+            events: tool_call_events(&tool_call_id, "confirm"),
+            ..RecordingAgent::default()
+        };
+
+        let result = agent.run_agent(&RunAgentParams::new(), ()).await.unwrap();
+        assert!(result.is_awaiting_tool_results());
+        assert_eq!(result.pending_tool_calls.len(), 1);
+        assert_eq!(result.pending_tool_calls[0].id, tool_call_id);
+        assert_eq!(result.pending_tool_calls[0].name, "confirm");
+
+        let agent = finishing_agent(JsonValue::Null);
+        let result = agent.run_agent(&RunAgentParams::new(), ()).await.unwrap();
+        assert!(!result.is_awaiting_tool_results());
+    }
+}
@@ -0,0 +1,160 @@
+//! Reassembly of audio streamed with the `AUDIO_START`, `AUDIO_CHUNK` and `AUDIO_END` custom
+//! events.
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures::Stream;
+use futures::channel::mpsc;
+use log::warn;
+
+use crate::agent::{AgentError, AgentStateMutation};
+use crate::core::event::CustomEvent;
+use crate::core::extensions::{AudioChunk, AudioEnd, AudioStart, CustomEventExtension};
+use crate::core::types::MessageId;
+use crate::core::{AgentState, FwdProps};
+use crate::subscriber::{AgentSubscriber, AgentSubscriberParams};
+
+/// The audio of a single message, as a stream of decoded chunks.
+///
+/// The stream ends when the `AUDIO_END` event for the message is received, or when the run is
+/// finalized.
+pub struct AudioStream {
+    /// The event that started the stream, describing its encoding
+    pub start: AudioStart,
+    chunks: mpsc::UnboundedReceiver<Bytes>,
+}
+
+impl Stream for AudioStream {
+    type Item = Bytes;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.chunks).poll_next(cx)
+    }
+}
+
+/// Subscriber reassembling streamed audio into an [`AudioStream`] per message.
+///
+/// ```no_run
+/// # use ag_ui_client::audio::AudioReassembler;
+/// let (reassembler, mut streams) = AudioReassembler::new();
+/// // Pass `reassembler` as a subscriber to the run, then consume each stream as it starts:
+/// // while let Some(stream) = streams.next().await { ... }
+/// ```
+pub struct AudioReassembler {
+    streams: Mutex<HashMap<MessageId, mpsc::UnboundedSender<Bytes>>>,
+    new_streams: mpsc::UnboundedSender<AudioStream>,
+}
+
+impl AudioReassembler {
+    /// Creates a reassembler, along with a receiver of the audio streams as they start.
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<AudioStream>) {
+        let (new_streams, receiver) = mpsc::unbounded();
+        let reassembler = Self {
+            streams: Mutex::new(HashMap::new()),
+            new_streams,
+        };
+        (reassembler, receiver)
+    }
+
+    fn handle_custom_event(&self, event: &CustomEvent) -> Result<(), serde_json::Error> {
+        let mut streams = self.streams.lock().unwrap();
+
+        if let Some(start) = AudioStart::from_custom_event(event) {
+            let start = start?;
+            let (sender, chunks) = mpsc::unbounded();
+            streams.insert(start.message_id.clone(), sender);
+            // Nobody listening for new streams is not an error
+            let _ = self
+                .new_streams
+                .unbounded_send(AudioStream { start, chunks });
+        } else if let Some(chunk) = AudioChunk::from_custom_event(event) {
+            let chunk = chunk?;
+            let Some(sender) = streams.get(&chunk.message_id) else {
+                warn!(
+                    "Ignoring audio chunk for unknown message {}",
+                    chunk.message_id
+                );
+                return Ok(());
+            };
+            match chunk.decode() {
+                Ok(data) => {
+                    let _ = sender.unbounded_send(Bytes::from(data));
+                }
+                Err(err) => warn!("Ignoring audio chunk with invalid data: {err}"),
+            }
+        } else if let Some(end) = AudioEnd::from_custom_event(event) {
+            streams.remove(&end?.message_id);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<StateT, FwdPropsT> AgentSubscriber<StateT, FwdPropsT> for AudioReassembler
+where
+    StateT: AgentState,
+    FwdPropsT: FwdProps,
+{
+    async fn on_run_finalized(
+        &self,
+        _params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<AgentStateMutation<StateT>, AgentError> {
+        // Dropping the senders ends any stream left open
+        self.streams.lock().unwrap().clear();
+        Ok(AgentStateMutation::default())
+    }
+
+    async fn on_custom_event(
+        &self,
+        event: &CustomEvent,
+        _params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<AgentStateMutation<StateT>, AgentError> {
+        if let Err(err) = self.handle_custom_event(event) {
+            warn!("Ignoring malformed {}: {err}", event.name);
+        }
+        Ok(AgentStateMutation::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::event::Event;
+    use crate::event_handler::testing::{handle_events, input, new_handler};
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_audio_is_reassembled_per_message() {
+        let input = input();
+        let (reassembler, mut streams) = AudioReassembler::new();
+        let mut handler = new_handler(&input, reassembler);
+
+        let message_id = MessageId::random();
+        let events: Vec<Event> = vec![
+            AudioStart::new(message_id.clone(), "pcm16", 24_000)
+                .into_event()
+                .unwrap(),
+            AudioChunk::new(message_id.clone(), [1, 2])
+                .into_event()
+                .unwrap(),
+            AudioChunk::new(message_id.clone(), [3])
+                .into_event()
+                .unwrap(),
+            AudioEnd::new(message_id.clone()).into_event().unwrap(),
+        ];
+        handle_events(&mut handler, &events).await;
+
+        let stream = streams.next().await.unwrap();
+        assert_eq!(stream.start.message_id, message_id);
+        assert_eq!(stream.start.sample_rate, 24_000);
+        let chunks: Vec<Bytes> = stream.collect().await;
+        assert_eq!(
+            chunks,
+            vec![Bytes::from_static(&[1, 2]), Bytes::from_static(&[3])]
+        );
+    }
+}
@@ -0,0 +1,616 @@
+//! Dispatch of events to slow subscribers through a queue, so that they do not hold up the run.
+//!
+//! Subscribers are awaited one after the other for every event, so a subscriber doing slow I/O
+//! delays the reading of the event stream. Implementing [`QueuedSubscriber`] and wrapped in a
+//! [`BufferedSubscriber`], it instead receives the events from a queue, drained by a worker running next to the run, and the
+//! [`LagPolicy`] decides what happens once the subscriber falls behind:
+//!
+//! ```no_run
+//! # use ag_ui_client::{Agent, HttpAgent, RunAgentParams};
+//! # use ag_ui_client::buffered::{BufferedSubscriber, LagPolicy, QueuedSubscriber};
+//! # struct SlowRenderer;
+//! # impl QueuedSubscriber for SlowRenderer {}
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let agent = HttpAgent::builder().with_url_str("http://127.0.0.1:3000/")?.build()?;
+//! let (renderer, worker) = BufferedSubscriber::new(SlowRenderer, 64, LagPolicy::default());
+//! let worker = tokio::spawn(worker);
+//! agent.run_agent(&RunAgentParams::new(), (renderer,)).await?;
+//! worker.await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Queuing an event never waits for the subscriber. The worker keeps its own copy of the
+//! messages and state, updated from the [`AgentChange`]s of every event, so that queuing an event
+//! costs a copy of the event rather than of the whole history. The messages and state are only
+//! copied in full at the start of a run, when they are replaced outright, such as by a subscriber
+//! returning new messages, and when changes are dropped under [`LagPolicy::DropDeltas`].
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::channel::mpsc;
+use futures::future::BoxFuture;
+use futures::{FutureExt, StreamExt};
+use log::warn;
+
+use crate::agent::{AgentChange, AgentError, AgentStateMutation};
+use crate::core::event::Event;
+use crate::core::types::{Message, RunAgentInput};
+use crate::core::{AgentState, FwdProps, JsonValue, StatePatcher};
+use crate::subscriber::{AgentSubscriber, AgentSubscriberParams};
+use crate::tool_calls::ToolCallTracker;
+
+/// What a [`BufferedSubscriber`] does with events once it lags `capacity` items behind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Keep queuing every event, without bound: memory grows for as long as the subscriber lags,
+    /// so only use it for subscribers known to catch up
+    Buffer,
+    /// Drop the content deltas, such as `TEXT_MESSAGE_CONTENT` and `TOOL_CALL_ARGS`, and keep
+    /// queuing all other events. The deltas a subscriber misses are still part of the messages
+    /// it receives with later events: rather than queuing the changes they make, the worker
+    /// receives a copy of the run along with the next queued event.
+    #[default]
+    DropDeltas,
+    /// Detach the subscriber, which receives no further events, and report it to the hook set
+    /// with [`BufferedSubscriber::with_detach_hook`]
+    Detach,
+}
+
+/// Callback invoked with the error describing why a subscriber was detached.
+pub type DetachHook = Arc<dyn Fn(&AgentError) + Send + Sync>;
+
+/// The worker's copy of the run
+struct Snapshot<StateT: AgentState, FwdPropsT: FwdProps> {
+    messages: Vec<Message>,
+    state: StateT,
+    input: RunAgentInput<StateT, FwdPropsT>,
+    tool_calls: ToolCallTracker,
+    /// Applies the state patches of the changes to `state`
+    state_patcher: StatePatcher,
+}
+
+impl<StateT: AgentState, FwdPropsT: FwdProps> Snapshot<StateT, FwdPropsT> {
+    fn new(params: AgentSubscriberParams<'_, StateT, FwdPropsT>) -> Self {
+        Self {
+            messages: params.messages.to_vec(),
+            state: params.state.clone(),
+            input: params.input.clone(),
+            // The tracker is shared, not copied
+            tool_calls: params.tool_calls.clone(),
+            state_patcher: StatePatcher::new(),
+        }
+    }
+
+    fn params(&self) -> AgentSubscriberParams<'_, StateT, FwdPropsT> {
+        AgentSubscriberParams {
+            messages: &self.messages,
+            state: &self.state,
+            input: &self.input,
+            tool_calls: &self.tool_calls,
+        }
+    }
+}
+
+/// An item queued for the worker
+enum Dispatch<StateT: AgentState, FwdPropsT: FwdProps> {
+    /// Replaces the worker's copy of the run
+    Sync(Box<Snapshot<StateT, FwdPropsT>>),
+    /// Changes to apply to the worker's copy of the run
+    Changes(Vec<AgentChange<StateT>>),
+    Event(Event<StateT>),
+    Failed(AgentError),
+    Finalized,
+}
+
+/// Subscriber receiving the events of a run through the queue of a [`BufferedSubscriber`].
+///
+/// Each event comes with the messages and state at the time of the event, and the run ends with
+/// [`QueuedSubscriber::on_run_failed`] or [`QueuedSubscriber::on_run_finalized`]. As the
+/// subscriber runs behind the run, it cannot change it, and its errors are logged.
+#[async_trait::async_trait]
+pub trait QueuedSubscriber<StateT = JsonValue, FwdPropsT = JsonValue>: Send + Sync
+where
+    StateT: AgentState,
+    FwdPropsT: FwdProps,
+{
+    async fn on_event(
+        &self,
+        _event: &Event<StateT>,
+        _params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<(), AgentError> {
+        Ok(())
+    }
+
+    async fn on_run_failed(
+        &self,
+        _error: &AgentError,
+        _params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<(), AgentError> {
+        Ok(())
+    }
+
+    async fn on_run_finalized(
+        &self,
+        _params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<(), AgentError> {
+        Ok(())
+    }
+}
+
+/// Subscriber passing events to a slow [`QueuedSubscriber`] through a queue.
+pub struct BufferedSubscriber<StateT: AgentState = JsonValue, FwdPropsT: FwdProps = JsonValue> {
+    /// The queue, until the subscriber is detached
+    sender: Mutex<Option<mpsc::UnboundedSender<Dispatch<StateT, FwdPropsT>>>>,
+    /// Items queued and not handled by the worker yet, events as well as changes and copies of
+    /// the run
+    queued: Arc<AtomicUsize>,
+    capacity: usize,
+    policy: LagPolicy,
+    detach_hook: Option<DetachHook>,
+    dropped: AtomicUsize,
+    /// Whether the worker's copy of the run must be replaced before the next event
+    unsynced: AtomicBool,
+}
+
+impl<StateT, FwdPropsT> BufferedSubscriber<StateT, FwdPropsT>
+where
+    StateT: AgentState,
+    FwdPropsT: FwdProps,
+{
+    /// Wraps `subscriber` behind a queue, whose [`LagPolicy`] applies once `capacity` items are
+    /// queued, returning the worker passing the queued events to it. The worker must be spawned
+    /// or otherwise polled for the subscriber to receive events, and completes once the wrapper
+    /// is dropped and the queue drained.
+    pub fn new<S>(
+        subscriber: S,
+        capacity: usize,
+        policy: LagPolicy,
+    ) -> (Self, BoxFuture<'static, ()>)
+    where
+        S: QueuedSubscriber<StateT, FwdPropsT> + 'static,
+    {
+        let (sender, mut receiver) = mpsc::unbounded();
+        let queued = Arc::new(AtomicUsize::new(0));
+        let handled = queued.clone();
+        let worker = async move {
+            let mut run: Option<Snapshot<StateT, FwdPropsT>> = None;
+            while let Some(dispatch) = receiver.next().await {
+                handled.fetch_sub(1, Ordering::Relaxed);
+                let dispatch = match (dispatch, &mut run) {
+                    (Dispatch::Sync(snapshot), run) => {
+                        *run = Some(*snapshot);
+                        continue;
+                    }
+                    (Dispatch::Changes(changes), Some(run)) => {
+                        for change in &changes {
+                            change.apply(&mut run.messages, &mut run.state, &mut run.state_patcher);
+                        }
+                        continue;
+                    }
+                    (dispatch, _) => dispatch,
+                };
+                // Every other item is preceded by a sync
+                let Some(run) = &run else { continue };
+                let result = match dispatch {
+                    Dispatch::Event(event) => subscriber.on_event(&event, run.params()).await,
+                    Dispatch::Failed(error) => subscriber.on_run_failed(&error, run.params()).await,
+                    Dispatch::Finalized => subscriber.on_run_finalized(run.params()).await,
+                    Dispatch::Sync(_) | Dispatch::Changes(_) => continue,
+                };
+                if let Err(err) = result {
+                    warn!("Buffered subscriber failed: {err}");
+                }
+            }
+        }
+        .boxed();
+
+        let buffered = Self {
+            sender: Mutex::new(Some(sender)),
+            queued,
+            capacity: capacity.max(1),
+            policy,
+            detach_hook: None,
+            dropped: AtomicUsize::new(0),
+            unsynced: AtomicBool::new(true),
+        };
+        (buffered, worker)
+    }
+
+    /// Set a callback invoked when the subscriber is detached under [`LagPolicy::Detach`]
+    pub fn with_detach_hook(mut self, hook: impl Fn(&AgentError) + Send + Sync + 'static) -> Self {
+        self.detach_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// The number of events dropped under [`LagPolicy::DropDeltas`]
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Queues an item for the worker, preceded by a copy of the run if the worker's is stale.
+    ///
+    /// `make` is only called once the policy has decided to queue the item, so that dropped
+    /// events are never copied.
+    fn dispatch(
+        &self,
+        params: AgentSubscriberParams<'_, StateT, FwdPropsT>,
+        delta: bool,
+        make: impl FnOnce() -> Dispatch<StateT, FwdPropsT>,
+    ) {
+        let mut guard = self.sender.lock().unwrap();
+        if guard.is_none() {
+            return;
+        }
+
+        if self.is_lagging() {
+            match self.policy {
+                LagPolicy::Buffer => {}
+                LagPolicy::DropDeltas if delta => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                LagPolicy::DropDeltas => {}
+                LagPolicy::Detach => return self.detach(&mut guard),
+            }
+        }
+
+        if self.unsynced.swap(false, Ordering::Relaxed) {
+            self.send(&mut guard, Dispatch::Sync(Box::new(Snapshot::new(params))));
+        }
+        self.send(&mut guard, make());
+    }
+
+    /// Forwards changes to the worker's copy of the run, unless it is replaced anyway
+    fn forward_changes(&self, changes: &[AgentChange<StateT>]) {
+        if self.unsynced.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut guard = self.sender.lock().unwrap();
+        if guard.is_none() {
+            return;
+        }
+
+        if self.is_lagging() {
+            match self.policy {
+                LagPolicy::Buffer => {}
+                // The worker's copy is replaced with the next queued event instead
+                LagPolicy::DropDeltas => return self.unsynced.store(true, Ordering::Relaxed),
+                LagPolicy::Detach => return self.detach(&mut guard),
+            }
+        }
+        self.send(&mut guard, Dispatch::Changes(changes.to_vec()));
+    }
+
+    fn is_lagging(&self) -> bool {
+        self.queued.load(Ordering::Relaxed) >= self.capacity
+    }
+
+    fn send(
+        &self,
+        sender: &mut Option<mpsc::UnboundedSender<Dispatch<StateT, FwdPropsT>>>,
+        dispatch: Dispatch<StateT, FwdPropsT>,
+    ) {
+        if let Some(tx) = sender.as_ref() {
+            self.queued.fetch_add(1, Ordering::Relaxed);
+            if tx.unbounded_send(dispatch).is_err() {
+                // The worker is gone, so nobody is listening anymore
+                *sender = None;
+            }
+        }
+    }
+
+    fn detach(&self, sender: &mut Option<mpsc::UnboundedSender<Dispatch<StateT, FwdPropsT>>>) {
+        *sender = None;
+        let err = AgentError::Subscriber {
+            message: format!(
+                "Detached subscriber lagging more than {} items behind",
+                self.capacity
+            ),
+        };
+        warn!("{err}");
+        if let Some(hook) = &self.detach_hook {
+            hook(&err);
+        }
+    }
+}
+
+/// Whether the event only adds to a message or tool call
+fn is_delta<StateT: AgentState>(event: &Event<StateT>) -> bool {
+    matches!(
+        event,
+        Event::TextMessageContent(_)
+            | Event::TextMessageChunk(_)
+            | Event::ThinkingTextMessageContent(_)
+            | Event::ToolCallArgs(_)
+            | Event::ToolCallChunk(_)
+    )
+}
+
+#[async_trait::async_trait]
+impl<StateT, FwdPropsT> AgentSubscriber<StateT, FwdPropsT> for BufferedSubscriber<StateT, FwdPropsT>
+where
+    StateT: AgentState,
+    FwdPropsT: FwdProps,
+{
+    async fn on_event(
+        &self,
+        event: &Event<StateT>,
+        params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<AgentStateMutation<StateT>, AgentError> {
+        self.dispatch(params, is_delta(event), || Dispatch::Event(event.clone()));
+        Ok(AgentStateMutation::default())
+    }
+
+    async fn on_changes(
+        &self,
+        changes: &[AgentChange<StateT>],
+        _params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<(), AgentError> {
+        self.forward_changes(changes);
+        Ok(())
+    }
+
+    async fn on_run_failed(
+        &self,
+        error: &AgentError,
+        params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<AgentStateMutation<StateT>, AgentError> {
+        // Errors are not cloneable, so the subscriber receives a description of the error
+        let error = AgentError::exec(error.to_string());
+        self.dispatch(params, false, || Dispatch::Failed(error));
+        Ok(AgentStateMutation::default())
+    }
+
+    async fn on_run_finalized(
+        &self,
+        params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<AgentStateMutation<StateT>, AgentError> {
+        self.dispatch(params, false, || Dispatch::Finalized);
+        // The next run starts from its own messages and state
+        self.unsynced.store(true, Ordering::Relaxed);
+        Ok(AgentStateMutation::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::event::{
+        BaseEvent, EventType, RunStartedEvent, StepStartedEvent, TextMessageContentEvent,
+        TextMessageEndEvent, TextMessageStartEvent,
+    };
+    use crate::core::extensions::{CustomEventExtension, MessagesDelta};
+    use crate::core::types::{MessageId, RunId, ThreadId};
+    use crate::event_handler::EventHandler;
+    use crate::event_handler::testing::{handle_events, input, new_handler};
+    use crate::subscriber::Subscribers;
+    use std::sync::Mutex as StdMutex;
+
+    /// The type of an event and the content of the last message as seen with it
+    type SeenEvent = (EventType, Option<String>);
+
+    /// Records the type of each event it receives, with the content of the last message as seen
+    /// with the event, and how the run ended
+    #[derive(Clone, Default)]
+    struct Recorder {
+        events: Arc<StdMutex<Vec<SeenEvent>>>,
+        finalized: Arc<AtomicUsize>,
+    }
+
+    impl Recorder {
+        fn event_types(&self) -> Vec<EventType> {
+            let events = self.events.lock().unwrap();
+            events.iter().map(|(event_type, _)| *event_type).collect()
+        }
+
+        fn contents(&self) -> Vec<Option<String>> {
+            let events = self.events.lock().unwrap();
+            events.iter().map(|(_, content)| content.clone()).collect()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl QueuedSubscriber for Recorder {
+        async fn on_event(
+            &self,
+            event: &Event,
+            params: AgentSubscriberParams<'async_trait, JsonValue, JsonValue>,
+        ) -> Result<(), AgentError> {
+            let content = params.messages.last().and_then(Message::content);
+            self.events
+                .lock()
+                .unwrap()
+                .push((event.event_type(), content.map(str::to_string)));
+            Ok(())
+        }
+
+        async fn on_run_finalized(
+            &self,
+            _params: AgentSubscriberParams<'async_trait, JsonValue, JsonValue>,
+        ) -> Result<(), AgentError> {
+            self.finalized.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn events() -> Vec<Event> {
+        let message_id = MessageId::random();
+        let content = |delta: &str| {
+            Event::TextMessageContent(
+                TextMessageContentEvent::new(message_id.clone(), delta.to_string()).unwrap(),
+            )
+        };
+        vec![
+            Event::RunStarted(RunStartedEvent {
+                base: BaseEvent {
+                    timestamp: None,
+                    raw_event: None,
+                },
+                thread_id: ThreadId::random(),
+                run_id: RunId::random(),
+            }),
+            Event::TextMessageStart(TextMessageStartEvent::new(message_id.clone())),
+            content("Hel"),
+            content("lo"),
+            Event::TextMessageEnd(TextMessageEndEvent {
+                base: BaseEvent {
+                    timestamp: None,
+                    raw_event: None,
+                },
+                message_id,
+            }),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_drop_deltas_keeps_lifecycle_events() {
+        let recorder = Recorder::default();
+        let (buffered, worker) =
+            BufferedSubscriber::new(recorder.clone(), 2, LagPolicy::DropDeltas);
+        let buffered = Arc::new(buffered);
+        let input = input();
+        let subscribers = Subscribers::new(vec![buffered.clone()]);
+        let mut handler = EventHandler::new(vec![], JsonValue::Null, &input, subscribers);
+
+        // The worker is not running yet, so the buffer fills up and the deltas are dropped
+        let events = events();
+        handle_events(&mut handler, &events[..4]).await;
+        assert_eq!(buffered.dropped(), 2);
+
+        // Other events are still queued
+        handler.handle_event(&events[4]).await.unwrap();
+        handler.on_finalize().await.unwrap();
+        let worker = tokio::spawn(worker);
+        drop(handler);
+        drop(buffered);
+        worker.await.unwrap();
+
+        // The content of the dropped deltas is still part of the messages seen with later events
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            vec![
+                (EventType::RunStarted, None),
+                (EventType::TextMessageStart, None),
+                (EventType::TextMessageEnd, Some("Hello".to_string())),
+            ]
+        );
+        assert_eq!(recorder.finalized.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_detach_lagging_subscriber() {
+        let recorder = Recorder::default();
+        let detached = Arc::new(AtomicUsize::new(0));
+        let counter = detached.clone();
+        let (buffered, worker) = BufferedSubscriber::new(recorder.clone(), 1, LagPolicy::Detach);
+        let buffered = buffered.with_detach_hook(move |err| {
+            assert!(matches!(err, AgentError::Subscriber { .. }));
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let input = input();
+        let mut handler = new_handler(&input, buffered);
+
+        handle_events(&mut handler, &events()).await;
+        handler.on_finalize().await.unwrap();
+        drop(handler);
+        worker.await;
+
+        // Nothing reaches the subscriber once detached, not even the end of the run
+        assert_eq!(detached.load(Ordering::SeqCst), 1);
+        assert_eq!(recorder.event_types(), vec![EventType::RunStarted]);
+        assert_eq!(recorder.finalized.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_buffer_follows_run_without_blocking() {
+        let recorder = Recorder::default();
+        let (buffered, worker) = BufferedSubscriber::new(recorder.clone(), 1, LagPolicy::Buffer);
+        let input = input();
+        let mut handler = new_handler(&input, buffered);
+
+        // The worker is not running, so every event is queued beyond the capacity
+        let mut events = events();
+        let replace = MessagesDelta::new(
+            serde_json::from_value(serde_json::json!([
+                {"op": "replace", "path": "/0/content", "value": "Bye"}
+            ]))
+            .unwrap(),
+        );
+        events.push(replace.into_event().unwrap());
+        events.push(Event::StepStarted(StepStartedEvent {
+            base: BaseEvent {
+                timestamp: None,
+                raw_event: None,
+            },
+            step_name: "next".to_string(),
+        }));
+        handle_events(&mut handler, &events).await;
+        drop(handler);
+        worker.await;
+
+        // Each event is seen with the messages before it, including after the messages were
+        // replaced outright
+        assert_eq!(
+            recorder.contents(),
+            vec![
+                None,
+                None,
+                Some(String::new()),
+                Some("Hel".to_string()),
+                Some("Hello".to_string()),
+                Some("Hello".to_string()),
+                Some("Bye".to_string()),
+            ]
+        );
+    }
+
+    /// Replaces the messages with the content of the last one in upper case
+    struct UppercaseSubscriber;
+
+    #[async_trait::async_trait]
+    impl AgentSubscriber for UppercaseSubscriber {
+        async fn on_text_message_content_event(
+            &self,
+            _event: &TextMessageContentEvent,
+            text_message_buffer: &str,
+            params: AgentSubscriberParams<'async_trait, JsonValue, JsonValue>,
+        ) -> Result<AgentStateMutation, AgentError> {
+            let mut messages = params.messages.to_vec();
+            if let Some(content) = messages.last_mut().and_then(Message::content_mut) {
+                *content = text_message_buffer.to_uppercase();
+            }
+            Ok(AgentStateMutation {
+                messages: Some(messages),
+                ..AgentStateMutation::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_buffer_follows_replacements_next_to_changes() {
+        let recorder = Recorder::default();
+        let (buffered, worker) = BufferedSubscriber::new(recorder.clone(), 16, LagPolicy::Buffer);
+        let input = input();
+        let subscribers = Subscribers::new(vec![Arc::new(buffered), Arc::new(UppercaseSubscriber)]);
+        let mut handler = EventHandler::new(vec![], JsonValue::Null, &input, subscribers);
+
+        // Each content event both appends to the message and replaces the messages
+        handle_events(&mut handler, &events()).await;
+        drop(handler);
+        worker.await;
+
+        assert_eq!(
+            recorder.contents(),
+            vec![
+                None,
+                None,
+                Some(String::new()),
+                Some("HEL".to_string()),
+                Some("HELLO".to_string()),
+            ]
+        );
+    }
+}
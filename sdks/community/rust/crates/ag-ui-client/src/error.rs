@@ -1,4 +1,6 @@
+use crate::core::event::EventType;
 use reqwest::StatusCode;
+use std::io::ErrorKind;
 use thiserror::Error;
 
 /// Ag-ui client errors
@@ -24,10 +26,46 @@ pub enum AgUiClientError {
     #[error("SSE parse error: {message}")]
     SseParse { message: String },
 
+    /// An SSE frame whose data could not be decoded into an event
+    #[error("Invalid event frame at byte offset {offset}: {source} (data: {snippet})")]
+    InvalidFrame {
+        /// Byte offset of the frame within the response body
+        offset: usize,
+        /// The start of the offending payload
+        snippet: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// No SSE frame was received within the configured maximum gap
+    #[error("Event stream stalled: no frame received within {max_gap:?}")]
+    Stalled { max_gap: std::time::Duration },
+
+    /// A rebuilt event stream started replaying the events already delivered, then yielded a
+    /// different event at `position`, such as a non-deterministic run started over
+    #[error("Rebuilt event stream diverged from the delivered events at event {position}")]
+    ReplayDiverged { position: usize },
+
+    /// A delta event referring to a message or tool call that has not been started
+    #[error("{event_type:?} event refers to {id}, which has not been started")]
+    MisattributedDelta { event_type: EventType, id: String },
+
     /// JSON serialization/deserialization errors
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 
+    /// A run result that does not have the expected type
+    #[error("Run result is not a valid {type_name}: {source}")]
+    InvalidResult {
+        type_name: &'static str,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// Errors from a conversation store
+    #[error("Conversation store error: {message}")]
+    Store { message: String },
+
     /// Errors from subscribers/callbacks
     #[error("Subscriber error: {message}")]
     Subscriber { message: String },
@@ -47,13 +85,17 @@ impl AgUiClientError {
 
     /// Whether or not the error is retryable.
     /// Generally, the request is considered retryable if the following errors are received:
-    /// - Connection errors
+    /// - Connection errors, including connections dropped while streaming the response
     /// - Timeout errors
     /// - Internal server errors
     /// - Errors related to too many requests (ie, rate limiting or throttling)
+    /// - Stalled event streams
     pub fn is_retryable(&self) -> bool {
         match self {
-            AgUiClientError::HttpTransport(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+            AgUiClientError::Stalled { .. } => true,
+            AgUiClientError::HttpTransport(e) => {
+                e.is_connect() || e.is_timeout() || e.is_request() || is_dropped_connection(e)
+            }
             AgUiClientError::HttpStatus { status, .. } => {
                 status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
             }
@@ -66,4 +108,22 @@ impl AgUiClientError {
     }
 }
 
+/// Whether a transport error was caused by the connection closing unexpectedly
+fn is_dropped_connection(err: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            return matches!(
+                io.kind(),
+                ErrorKind::UnexpectedEof
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::BrokenPipe
+            );
+        }
+        source = err.source();
+    }
+    false
+}
+
 pub type Result<T> = std::result::Result<T, AgUiClientError>;
@@ -1,13 +1,29 @@
-use crate::agent::{AgentError, AgentStateMutation};
-use crate::core::event::Event;
-use crate::core::types::{FunctionCall, Message, MessageId, Role, RunAgentInput, ToolCall};
-use crate::core::{AgentState, FwdProps, JsonValue};
+use crate::agent::{AgentChange, AgentError, AgentStateMutation};
+use crate::core::event::{Event, EventType};
+use crate::core::extensions::{
+    Citation, CustomEventExtension, MessageRevision, MessagesDelta, ToolResultDelta,
+};
+use crate::core::partial_json::parse_partial;
+use crate::core::types::{
+    FunctionCall, Message, MessageId, Role, RunAgentInput, ToolCall, ToolCallId,
+};
+use crate::core::{AgentState, FwdProps, JsonValue, StatePatcher};
+use crate::messages_delta::apply_messages_patch;
 use crate::subscriber::{AgentSubscriberParams, Subscribers};
-use json_patch::PatchOperation;
-use log::error;
+use crate::tool_calls::ToolCallTracker;
+use log::{error, warn};
 use std::collections::{HashMap, HashSet};
 
 /// Captures the run state and handles events
+///
+/// The default behavior for each event, as well as incremental changes returned by subscribers,
+/// updates `messages` and `state` in place and records the [`AgentChange`]s made, rather than
+/// returning a copy of them in the mutation. Subscribers are notified of the recorded changes
+/// through [`EventHandler::apply_mutation`].
+///
+/// Events for different messages and tool calls may be interleaved: deltas are applied to the
+/// message or tool call they name rather than to the most recent one. A delta naming a message
+/// or tool call that has not been started fails with [`AgentError::MisattributedDelta`].
 #[derive(Clone)]
 pub(crate) struct EventHandler<'a, StateT, FwdPropsT>
 where
@@ -19,6 +35,15 @@ where
     pub input: &'a RunAgentInput<StateT, FwdPropsT>,
     pub subscribers: Subscribers<StateT, FwdPropsT>,
     pub result: JsonValue,
+    /// Changes made since the last applied mutation
+    changes: Vec<AgentChange<StateT>>,
+    /// Output streamed so far for tool calls without a result yet
+    tool_result_buffers: HashMap<ToolCallId, String>,
+    /// Citations received so far, by the message they annotate
+    citations: HashMap<MessageId, Vec<Citation>>,
+    /// Applies state deltas, reset whenever the state is replaced by other means
+    state_patcher: StatePatcher,
+    pub tool_calls: ToolCallTracker,
 }
 
 impl<'a, StateT, FwdPropsT> EventHandler<'a, StateT, FwdPropsT>
@@ -33,50 +58,66 @@ where
         subscribers: Subscribers<StateT, FwdPropsT>,
     ) -> Self {
         Self {
+            tool_calls: ToolCallTracker::from_messages(&messages),
             messages,
             state,
             input,
             subscribers,
             result: JsonValue::Null,
+            changes: Vec::new(),
+            tool_result_buffers: HashMap::new(),
+            citations: HashMap::new(),
+            state_patcher: StatePatcher::new(),
         }
     }
 
+    // Finds the most recent message with the given id
+    fn message(&self, id: &MessageId) -> Option<&Message> {
+        self.messages.iter().rfind(|m| m.id() == id)
+    }
+
+    // Finds the most recent tool call with the given id
+    fn tool_call(&self, id: &ToolCallId) -> Option<&ToolCall> {
+        self.messages
+            .iter()
+            .rev()
+            .filter_map(|m| m.tool_calls())
+            .flatten()
+            .find(|tc| tc.id == *id)
+    }
+
     fn to_subscriber_params(&'a self) -> AgentSubscriberParams<'a, StateT, FwdPropsT> {
         AgentSubscriberParams {
             messages: &self.messages,
             state: &self.state,
             input: self.input,
+            tool_calls: &self.tool_calls,
         }
     }
 
-    // Helper method to directly update state and messages without using apply_mutation
-    fn update_from_mutation(&mut self, mutation: &AgentStateMutation<StateT>) {
-        if let Some(messages) = &mutation.messages {
-            self.messages = messages.clone();
-        }
-        if let Some(state) = &mutation.state {
-            self.state = state.clone();
-        }
+    // Helper method to apply a change and record it for notification
+    fn record(&mut self, change: AgentChange<StateT>) {
+        change.apply(&mut self.messages, &mut self.state, &mut self.state_patcher);
+        self.changes.push(change);
     }
 
     // Helper method to process a subscriber's mutation
     fn process_mutation(
         &mut self,
-        mutation: AgentStateMutation<StateT>,
+        mut mutation: AgentStateMutation<StateT>,
         current_mutation: &mut AgentStateMutation<StateT>,
     ) {
-        // Apply any mutations
-        if mutation.messages.is_some() || mutation.state.is_some() {
-            // Update directly without using apply_mutation
-            self.update_from_mutation(&mutation);
-
-            // Update current_mutation with the applied changes
-            if mutation.messages.is_some() {
-                current_mutation.messages = mutation.messages;
-            }
-            if mutation.state.is_some() {
-                current_mutation.state = mutation.state;
-            }
+        // Outright replacements are recorded as changes too, followed by the incremental changes
+        if let Some(messages) = mutation.messages.take() {
+            self.record(AgentChange::SetMessages(messages.clone()));
+            current_mutation.messages = Some(messages);
+        }
+        if let Some(state) = mutation.state.take() {
+            self.record(AgentChange::SetState(state.clone()));
+            current_mutation.state = Some(state);
+        }
+        for change in mutation.changes {
+            self.record(change);
         }
     }
 
@@ -84,11 +125,15 @@ where
         &mut self,
         event: &Event<StateT>,
     ) -> Result<AgentStateMutation<StateT>, AgentError> {
+        if let Event::Unknown { type_name, .. } = event {
+            warn!("Skipping event of unknown type {type_name}");
+            return Ok(AgentStateMutation::default());
+        }
+
         let mut current_mutation = AgentStateMutation::default();
         let mut mutations = Vec::new();
 
-        // Clone subscribers to avoid borrowing issues
-        for subscriber in &self.subscribers.clone() {
+        for subscriber in &self.subscribers {
             let params = self.to_subscriber_params();
             let mutation = subscriber.on_event(event, params).await?;
             mutations.push(mutation);
@@ -103,9 +148,9 @@ where
                     content: Some(String::new()),
                     name: None,
                     tool_calls: None,
+                    metadata: None,
                 };
-                self.messages.push(new_message);
-                current_mutation.messages = Some(self.messages.clone());
+                self.record(AgentChange::Append(new_message));
 
                 for subscriber in &self.subscribers {
                     let params = self.to_subscriber_params();
@@ -115,26 +160,27 @@ where
             }
             Event::TextMessageContent(e) => {
                 // Default behavior
-                if let Some(last_message) = self.messages.last_mut() {
-                    let content = last_message.content_mut();
-                    if let Some(s) = content {
-                        s.push_str(&e.delta)
-                    }
-                    current_mutation.messages = Some(self.messages.clone());
+                if self.message(&e.message_id).is_none() {
+                    return Err(AgentError::MisattributedDelta {
+                        event_type: EventType::TextMessageContent,
+                        id: e.message_id.to_string(),
+                    });
                 }
+                self.record(AgentChange::UpdateContent {
+                    id: e.message_id.clone(),
+                    delta: e.delta.clone(),
+                });
 
                 // Get the current text message buffer
                 let text_message_buffer = self
-                    .messages
-                    .last()
+                    .message(&e.message_id)
                     .and_then(|m| m.content())
-                    .unwrap_or_default()
-                    .to_string(); // Clone to avoid borrowing issues
+                    .unwrap_or_default();
 
                 for subscriber in &self.subscribers {
                     let params = self.to_subscriber_params();
                     let mutation = subscriber
-                        .on_text_message_content_event(e, &text_message_buffer, params)
+                        .on_text_message_content_event(e, text_message_buffer, params)
                         .await?;
                     mutations.push(mutation);
                 }
@@ -142,16 +188,14 @@ where
             Event::TextMessageEnd(e) => {
                 // Get the current text message buffer
                 let text_message_buffer = self
-                    .messages
-                    .last()
+                    .message(&e.message_id)
                     .and_then(|m| m.content())
-                    .unwrap_or_default()
-                    .to_string(); // Clone to avoid borrowing issues
+                    .unwrap_or_default();
 
                 for subscriber in &self.subscribers {
                     let params = self.to_subscriber_params();
                     let mutation = subscriber
-                        .on_text_message_end_event(e, &text_message_buffer, params)
+                        .on_text_message_end_event(e, text_message_buffer, params)
                         .await?;
                     mutations.push(mutation);
                 }
@@ -201,27 +245,37 @@ where
                     },
                 };
 
-                if let Some(last_message) = self.messages.last_mut() {
-                    if Some(last_message.id()) == e.parent_message_id.clone().as_ref() {
-                        let _ = last_message.tool_calls_mut().get_or_insert(&mut Vec::new());
-
-                        let _ = last_message
-                            .tool_calls_mut()
-                            .map(|tc| tc.push(new_tool_call));
+                // Attach the tool call to its parent message, starting one if needed
+                let parent_message_id = e
+                    .parent_message_id
+                    .as_ref()
+                    .filter(|id| self.message(id).is_some())
+                    .cloned();
+                match parent_message_id {
+                    Some(message_id) => self.record(AgentChange::AppendToolCall {
+                        message_id,
+                        tool_call: new_tool_call,
+                    }),
+                    None => {
+                        let new_message = Message::Assistant {
+                            id: e
+                                .parent_message_id
+                                .clone()
+                                .unwrap_or_else(MessageId::random),
+                            content: None,
+                            name: None,
+                            tool_calls: Some(vec![new_tool_call]),
+                            metadata: None,
+                        };
+                        self.record(AgentChange::Append(new_message));
                     }
-                } else {
-                    let new_message = Message::Assistant {
-                        id: e
-                            .parent_message_id
-                            .clone()
-                            .unwrap_or_else(MessageId::random),
-                        content: None,
-                        name: None,
-                        tool_calls: None,
-                    };
-                    self.messages.push(new_message);
                 }
-                current_mutation.messages = Some(self.messages.clone());
+
+                self.tool_calls.start(
+                    &e.tool_call_id,
+                    &e.tool_call_name,
+                    e.parent_message_id.as_ref(),
+                );
 
                 for subscriber in &self.subscribers {
                     let params = self.to_subscriber_params();
@@ -231,47 +285,42 @@ where
             }
             Event::ToolCallArgs(e) => {
                 // Default behavior
-                if let Some(last_message) = self.messages.last_mut()
-                    && let Some(tool_calls) = last_message.tool_calls_mut()
-                    && let Some(last_tool_call) = tool_calls.last_mut()
-                {
-                    last_tool_call.function.arguments.push_str(&e.delta);
-                    current_mutation.messages = Some(self.messages.clone());
+                if self.tool_call(&e.tool_call_id).is_none() {
+                    return Err(AgentError::MisattributedDelta {
+                        event_type: EventType::ToolCallArgs,
+                        id: e.tool_call_id.to_string(),
+                    });
                 }
+                self.record(AgentChange::UpdateToolCallArgs {
+                    tool_call_id: e.tool_call_id.clone(),
+                    delta: e.delta.clone(),
+                });
+                self.tool_calls.append_args(&e.tool_call_id, &e.delta);
 
                 // Get the current tool call buffer and name
-                let (tool_call_buffer, tool_call_name, partial_args) = if let Some(last_message) =
-                    self.messages.last()
-                {
-                    if let Some(tool_calls) = last_message.tool_calls() {
-                        if let Some(last_tool_call) = tool_calls.last() {
-                            // Try to parse the arguments as JSON to get partial args
-                            let partial_args = serde_json::from_str::<HashMap<String, JsonValue>>(
-                                &last_tool_call.function.arguments,
-                            )
-                            .unwrap_or_default();
+                let (tool_call_buffer, tool_call_name, partial_args) =
+                    match self.tool_call(&e.tool_call_id) {
+                        Some(tool_call) => {
+                            // Complete the arguments received so far to get partial args
+                            let partial_args = parse_partial(&tool_call.function.arguments)
+                                .and_then(|args| serde_json::from_value(args).ok())
+                                .unwrap_or_default();
                             (
-                                last_tool_call.function.arguments.clone(),
-                                last_tool_call.function.name.clone(),
+                                tool_call.function.arguments.as_str(),
+                                tool_call.function.name.as_str(),
                                 partial_args,
                             )
-                        } else {
-                            (String::new(), String::new(), HashMap::new())
                         }
-                    } else {
-                        (String::new(), String::new(), HashMap::new())
-                    }
-                } else {
-                    (String::new(), String::new(), HashMap::new())
-                };
+                        None => ("", "", HashMap::new()),
+                    };
 
                 for subscriber in &self.subscribers {
                     let params = self.to_subscriber_params();
                     let mutation = subscriber
                         .on_tool_call_args_event(
                             e,
-                            &tool_call_buffer,
-                            &tool_call_name,
+                            tool_call_buffer,
+                            tool_call_name,
                             &partial_args,
                             params,
                         )
@@ -280,31 +329,25 @@ where
                 }
             }
             Event::ToolCallEnd(e) => {
-                // Get the current tool call buffer and name
-                let (tool_call_name, tool_call_args) =
-                    if let Some(last_message) = self.messages.last() {
-                        if let Some(tool_calls) = last_message.tool_calls() {
-                            if let Some(last_tool_call) = tool_calls.last() {
-                                // Try to parse the arguments as JSON
-                                let args = serde_json::from_str::<HashMap<String, JsonValue>>(
-                                    &last_tool_call.function.arguments,
-                                )
-                                .unwrap_or_default();
-                                (last_tool_call.function.name.clone(), args)
-                            } else {
-                                (String::new(), HashMap::new())
-                            }
-                        } else {
-                            (String::new(), HashMap::new())
-                        }
-                    } else {
-                        (String::new(), HashMap::new())
-                    };
+                self.tool_calls.end(&e.tool_call_id);
+
+                // Get the current tool call name and arguments
+                let (tool_call_name, tool_call_args) = match self.tool_call(&e.tool_call_id) {
+                    Some(tool_call) => {
+                        // Try to parse the arguments as JSON
+                        let args = serde_json::from_str::<HashMap<String, JsonValue>>(
+                            &tool_call.function.arguments,
+                        )
+                        .unwrap_or_default();
+                        (tool_call.function.name.as_str(), args)
+                    }
+                    None => ("", HashMap::new()),
+                };
 
                 for subscriber in &self.subscribers {
                     let params = self.to_subscriber_params();
                     let mutation = subscriber
-                        .on_tool_call_end_event(e, &tool_call_name, &tool_call_args, params)
+                        .on_tool_call_end_event(e, tool_call_name, &tool_call_args, params)
                         .await?;
                     mutations.push(mutation);
                 }
@@ -317,6 +360,10 @@ where
                 }
             }
             Event::ToolCallResult(e) => {
+                // The result closes any stream of partial output
+                self.tool_result_buffers.remove(&e.tool_call_id);
+                self.tool_calls.resolve(&e.tool_call_id, &e.content);
+
                 for subscriber in &self.subscribers {
                     let params = self.to_subscriber_params();
                     let mutation = subscriber.on_tool_call_result_event(e, params).await?;
@@ -339,8 +386,7 @@ where
             }
             Event::StateSnapshot(e) => {
                 // Default behavior
-                self.state = e.snapshot.clone();
-                current_mutation.state = Some(self.state.clone());
+                self.record(AgentChange::SetState(e.snapshot.clone()));
 
                 for subscriber in &self.subscribers {
                     let params = self.to_subscriber_params();
@@ -350,20 +396,13 @@ where
             }
             Event::StateDelta(e) => {
                 // Default behavior
-                let mut state_val = serde_json::to_value(&self.state)?;
-
-                // TODO: This cast to and from JsonValue seems unnecessary
-                let patches: Vec<PatchOperation> =
-                    serde_json::from_value(serde_json::to_value(e.delta.clone())?)?;
-
-                json_patch::patch(&mut state_val, &patches).map_err(|err| {
-                    AgentError::Execution {
-                        message: format!("Failed to apply state patch: {err}"),
-                    }
-                })?;
-                let new_state: StateT = serde_json::from_value(state_val)?;
-                self.state = new_state;
-                current_mutation.state = Some(self.state.clone());
+                self.state_patcher
+                    .apply(&mut self.state, &e.delta)
+                    .map_err(|err| AgentError::Execution {
+                        message: err.message,
+                    })?;
+                // Recorded without `record`, as a patch that does not apply fails the run
+                self.changes.push(AgentChange::PatchState(e.delta.clone()));
 
                 for subscriber in &self.subscribers {
                     let params = self.to_subscriber_params();
@@ -386,11 +425,126 @@ where
                 }
             }
             Event::Custom(e) => {
+                // Default behavior
+                // A delta that cannot be applied fails the run, like a failing `STATE_DELTA`, as
+                // the messages would no longer match those of the agent
+                if let Some(delta) = MessagesDelta::from_custom_event(e) {
+                    let delta = delta.map_err(|err| AgentError::Execution {
+                        message: format!("Malformed {}: {err}", e.name),
+                    })?;
+                    // Applied in place, as changes to the messages it touches
+                    let changes =
+                        apply_messages_patch(&mut self.messages, &delta.delta).map_err(|err| {
+                            AgentError::Execution {
+                                message: format!("Failed to apply messages patch: {err}"),
+                            }
+                        })?;
+                    self.changes.extend(changes);
+                }
+
+                let revision = match MessageRevision::from_custom_event(e) {
+                    Some(Ok(revision)) => {
+                        let Some(message) = self.message(&revision.message_id) else {
+                            return Err(AgentError::MisattributedDelta {
+                                event_type: EventType::Custom,
+                                id: revision.message_id.to_string(),
+                            });
+                        };
+                        let previous_content = message.content().unwrap_or_default().to_string();
+                        match revision.apply(&previous_content) {
+                            Some(content) => {
+                                self.record(AgentChange::ReviseContent {
+                                    id: revision.message_id.clone(),
+                                    content,
+                                });
+                                // Keep the citations pointing at the text they cite
+                                if let Some(citations) =
+                                    self.citations.get_mut(&revision.message_id)
+                                {
+                                    citations.retain_mut(|citation| {
+                                        match revision.revise_range(citation.range()) {
+                                            Some(range) => {
+                                                citation.start = range.start;
+                                                citation.end = range.end;
+                                                true
+                                            }
+                                            None => false,
+                                        }
+                                    });
+                                }
+                                Some((revision, previous_content))
+                            }
+                            None => {
+                                warn!("Ignoring {} with edits outside of the message", e.name);
+                                None
+                            }
+                        }
+                    }
+                    Some(Err(err)) => {
+                        warn!("Ignoring malformed {}: {err}", e.name);
+                        None
+                    }
+                    None => None,
+                };
+
                 for subscriber in &self.subscribers {
                     let params = self.to_subscriber_params();
                     let mutation = subscriber.on_custom_event(e, params).await?;
                     mutations.push(mutation);
                 }
+
+                if let Some((revision, previous_content)) = &revision {
+                    for subscriber in &self.subscribers {
+                        let params = self.to_subscriber_params();
+                        let mutation = subscriber
+                            .on_message_revision(revision, previous_content, params)
+                            .await?;
+                        mutations.push(mutation);
+                    }
+                }
+
+                match ToolResultDelta::from_custom_event(e) {
+                    Some(Ok(delta)) => {
+                        self.tool_result_buffers
+                            .entry(delta.tool_call_id.clone())
+                            .or_default()
+                            .push_str(&delta.delta);
+                        let tool_result_buffer =
+                            self.tool_result_buffers[&delta.tool_call_id].as_str();
+
+                        for subscriber in &self.subscribers {
+                            let params = self.to_subscriber_params();
+                            let mutation = subscriber
+                                .on_tool_result_delta(&delta, tool_result_buffer, params)
+                                .await?;
+                            mutations.push(mutation);
+                        }
+                    }
+                    Some(Err(err)) => warn!("Ignoring malformed {}: {err}", e.name),
+                    None => {}
+                }
+
+                match Citation::from_custom_event(e) {
+                    Some(Ok(citation)) => {
+                        let message_id = citation.message_id.clone();
+                        self.citations
+                            .entry(message_id.clone())
+                            .or_default()
+                            .push(citation);
+                        let message_citations = self.citations[&message_id].as_slice();
+                        let citation = message_citations.last().unwrap();
+
+                        for subscriber in &self.subscribers {
+                            let params = self.to_subscriber_params();
+                            let mutation = subscriber
+                                .on_citation(citation, message_citations, params)
+                                .await?;
+                            mutations.push(mutation);
+                        }
+                    }
+                    Some(Err(err)) => warn!("Ignoring malformed {}: {err}", e.name),
+                    None => {}
+                }
             }
             Event::RunStarted(e) => {
                 for subscriber in &self.subscribers {
@@ -430,14 +584,18 @@ where
                     mutations.push(mutation);
                 }
             }
+            // Unknown events were skipped above
+            _ => {}
         }
 
         for mutation in mutations {
-            if mutation.stop_propagation {
-                self.update_from_mutation(&mutation);
-                return Ok(mutation);
-            } else {
-                self.process_mutation(mutation, &mut current_mutation);
+            let stop_propagation = mutation.stop_propagation;
+            self.process_mutation(mutation, &mut current_mutation);
+            if stop_propagation {
+                // The mutations of later subscribers are skipped, but the changes already made,
+                // including those of the default behavior, are still reported
+                current_mutation.stop_propagation = true;
+                break;
             }
         }
 
@@ -448,49 +606,87 @@ where
         &mut self,
         mutation: AgentStateMutation<StateT>,
     ) -> Result<(), AgentError> {
+        let changes = std::mem::take(&mut self.changes);
+        let mut messages_changed = changes.iter().any(AgentChange::is_message_change);
+        let mut state_changed = changes.iter().any(|c| !c.is_message_change());
+
         if let Some(messages) = mutation.messages {
             // Check for new messages to notify about
-            let old_message_ids: HashSet<&MessageId> =
-                self.messages.iter().map(|m| m.id()).collect();
-
-            let new_messages: Vec<&Message> = messages
-                .iter()
-                .filter(|m| !old_message_ids.contains(m.id()))
-                .collect();
+            let new_message_indices: Vec<usize> = {
+                let old_message_ids: HashSet<&MessageId> =
+                    self.messages.iter().map(|m| m.id()).collect();
+                messages
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, m)| !old_message_ids.contains(m.id()))
+                    .map(|(i, _)| i)
+                    .collect()
+            };
 
             // Set the new messages first
-            self.messages = messages.clone();
+            self.messages = messages;
+            messages_changed = true;
 
             // Notify about new messages
-            for message in new_messages {
-                self.notify_new_message(message).await?;
+            for i in new_message_indices {
+                self.notify_new_message(&self.messages[i]).await?;
+            }
+        }
 
-                // If the message is from assistant and has tool calls, notify about those too
-                if message.role() == Role::Assistant && message.tool_calls().is_some() {
-                    for tool_call in message.tool_calls().unwrap() {
-                        self.notify_new_tool_call(tool_call).await?;
-                    }
+        if let Some(state) = mutation.state {
+            self.state = state;
+            self.state_patcher.reset();
+            state_changed = true;
+        }
+
+        if !changes.is_empty() {
+            self.notify_changes(&changes).await?;
+        }
+
+        // Notify about messages and tool calls added incrementally
+        for change in &changes {
+            match change {
+                AgentChange::Append(message) => self.notify_new_message(message).await?,
+                AgentChange::AppendToolCall { tool_call, .. } => {
+                    self.notify_new_tool_call(tool_call).await?
                 }
+                _ => {}
             }
+        }
 
-            // Then notify about messages changed
+        if messages_changed {
             self.notify_messages_changed().await?;
         }
-
-        if let Some(state) = mutation.state {
-            self.state = state;
+        if state_changed {
             self.notify_state_changed().await?;
         }
 
         Ok(())
     }
 
+    async fn notify_changes(&self, changes: &[AgentChange<StateT>]) -> Result<(), AgentError> {
+        for subscriber in &self.subscribers {
+            subscriber
+                .on_changes(changes, self.to_subscriber_params())
+                .await?;
+        }
+        Ok(())
+    }
+
+    // Notifies about a new message and, for assistant messages, the tool calls it contains
     async fn notify_new_message(&self, message: &Message) -> Result<(), AgentError> {
         for subscriber in &self.subscribers {
             subscriber
                 .on_new_message(message, self.to_subscriber_params())
                 .await?;
         }
+        if message.role() == Role::Assistant
+            && let Some(tool_calls) = message.tool_calls()
+        {
+            for tool_call in tool_calls {
+                self.notify_new_tool_call(tool_call).await?;
+            }
+        }
         Ok(())
     }
 
@@ -540,3 +736,750 @@ where
         Ok(())
     }
 }
+
+/// Helpers for the tests driving an [`EventHandler`] directly, as [`crate::agent::Agent::run_agent`]
+/// does
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::EventHandler;
+    use crate::agent::RunAgentParams;
+    use crate::core::event::Event;
+    use crate::core::types::{RunAgentInput, RunId, ThreadId};
+    use crate::core::{AgentState, FwdProps, JsonValue};
+    use crate::subscriber::{AgentSubscriber, Subscribers};
+
+    /// An input with random ids, carrying the state, messages, tools, context and forwarded props
+    /// of `params`
+    pub(crate) fn input_from<StateT: AgentState, FwdPropsT: FwdProps>(
+        params: RunAgentParams<StateT, FwdPropsT>,
+    ) -> RunAgentInput<StateT, FwdPropsT> {
+        RunAgentInput::new(
+            ThreadId::random(),
+            RunId::random(),
+            params.state,
+            params.messages,
+            params.tools,
+            params.context,
+            params.forwarded_props,
+        )
+    }
+
+    /// An input without state, messages or tools
+    pub(crate) fn input() -> RunAgentInput {
+        input_from(RunAgentParams::new())
+    }
+
+    /// A handler without messages or state, notifying a single subscriber
+    pub(crate) fn new_handler(
+        input: &RunAgentInput,
+        subscriber: impl AgentSubscriber + 'static,
+    ) -> EventHandler<'_, JsonValue, JsonValue> {
+        EventHandler::new(
+            vec![],
+            JsonValue::Null,
+            input,
+            Subscribers::from_subscriber(subscriber),
+        )
+    }
+
+    /// Handles the events in order, applying their mutations as a run does
+    pub(crate) async fn handle_events<StateT: AgentState, FwdPropsT: FwdProps>(
+        handler: &mut EventHandler<'_, StateT, FwdPropsT>,
+        events: &[Event<StateT>],
+    ) {
+        for event in events {
+            let mutation = handler.handle_event(event).await.unwrap();
+            handler.apply_mutation(mutation).await.unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::{handle_events, input, new_handler};
+    use super::*;
+    use crate::core::event::{
+        BaseEvent, CustomEvent, StateDeltaEvent, StateSnapshotEvent, TextMessageContentEvent,
+        TextMessageStartEvent, ToolCallArgsEvent, ToolCallResultEvent, ToolCallStartEvent,
+    };
+    use crate::core::extensions::TextEdit;
+    use crate::subscriber::AgentSubscriber;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct RecordingSubscriber {
+        changes: Arc<Mutex<Vec<AgentChange>>>,
+        new_messages: Arc<Mutex<usize>>,
+        messages_changed: Arc<Mutex<usize>>,
+    }
+
+    #[async_trait::async_trait]
+    impl AgentSubscriber for RecordingSubscriber {
+        async fn on_changes(
+            &self,
+            changes: &[AgentChange],
+            _params: AgentSubscriberParams<'async_trait, JsonValue, JsonValue>,
+        ) -> Result<(), AgentError> {
+            self.changes.lock().unwrap().extend_from_slice(changes);
+            Ok(())
+        }
+
+        async fn on_new_message(
+            &self,
+            _message: &Message,
+            _params: AgentSubscriberParams<'async_trait, JsonValue, JsonValue>,
+        ) -> Result<(), AgentError> {
+            *self.new_messages.lock().unwrap() += 1;
+            Ok(())
+        }
+
+        async fn on_messages_changed(
+            &self,
+            _params: AgentSubscriberParams<'async_trait, JsonValue, JsonValue>,
+        ) -> Result<(), AgentError> {
+            *self.messages_changed.lock().unwrap() += 1;
+            Ok(())
+        }
+    }
+
+    /// Appends a fixed suffix to every text message start
+    struct SuffixSubscriber;
+
+    #[async_trait::async_trait]
+    impl AgentSubscriber for SuffixSubscriber {
+        async fn on_text_message_start_event(
+            &self,
+            event: &TextMessageStartEvent,
+            _params: AgentSubscriberParams<'async_trait, JsonValue, JsonValue>,
+        ) -> Result<AgentStateMutation, AgentError> {
+            Ok(AgentStateMutation::from_changes(vec![
+                AgentChange::UpdateContent {
+                    id: event.message_id.clone(),
+                    delta: ">".to_string(),
+                },
+            ]))
+        }
+    }
+
+    fn text_events(message_id: &MessageId) -> Vec<Event> {
+        vec![
+            Event::TextMessageStart(TextMessageStartEvent::new(message_id.clone())),
+            Event::TextMessageContent(
+                TextMessageContentEvent::new(message_id.clone(), "Hello".to_string()).unwrap(),
+            ),
+            Event::TextMessageContent(
+                TextMessageContentEvent::new(message_id.clone(), ", world".to_string()).unwrap(),
+            ),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_default_behavior_notifies_incremental_changes() {
+        let input = input();
+        let subscriber = RecordingSubscriber::default();
+        let changes = subscriber.changes.clone();
+        let new_messages = subscriber.new_messages.clone();
+        let messages_changed = subscriber.messages_changed.clone();
+        let mut handler = new_handler(&input, subscriber);
+
+        let message_id = MessageId::random();
+        for event in &text_events(&message_id) {
+            let mutation = handler.handle_event(event).await.unwrap();
+            assert!(mutation.messages.is_none());
+            handler.apply_mutation(mutation).await.unwrap();
+        }
+
+        assert_eq!(*messages_changed.lock().unwrap(), 3);
+        assert_eq!(*new_messages.lock().unwrap(), 1);
+        let changes = changes.lock().unwrap();
+        assert_eq!(changes.len(), 3);
+        assert!(matches!(&changes[0], AgentChange::Append(m) if *m.id() == message_id));
+        assert_eq!(
+            changes[2],
+            AgentChange::UpdateContent {
+                id: message_id,
+                delta: ", world".to_string()
+            }
+        );
+        assert_eq!(handler.messages.len(), 1);
+        assert_eq!(handler.messages[0].content(), Some("Hello, world"));
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_incremental_changes_are_applied() {
+        let input = input();
+        let mut handler = new_handler(&input, SuffixSubscriber);
+
+        handle_events(&mut handler, &text_events(&MessageId::random())).await;
+
+        assert_eq!(handler.messages[0].content(), Some(">Hello, world"));
+    }
+
+    /// Appends "!" to every text message content and stops propagation
+    struct StoppingSubscriber;
+
+    #[async_trait::async_trait]
+    impl AgentSubscriber for StoppingSubscriber {
+        async fn on_text_message_content_event(
+            &self,
+            event: &TextMessageContentEvent,
+            _text_message_buffer: &str,
+            _params: AgentSubscriberParams<'async_trait, JsonValue, JsonValue>,
+        ) -> Result<AgentStateMutation, AgentError> {
+            Ok(AgentStateMutation {
+                stop_propagation: true,
+                ..AgentStateMutation::from_changes(vec![AgentChange::UpdateContent {
+                    id: event.message_id.clone(),
+                    delta: "!".to_string(),
+                }])
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_propagation_reports_applied_changes() {
+        let input = input();
+        let recording = RecordingSubscriber::default();
+        let changes = recording.changes.clone();
+        let subscribers = Subscribers::new(vec![
+            Arc::new(recording),
+            Arc::new(StoppingSubscriber),
+            // Skipped, as the previous subscriber stops propagation
+            Arc::new(StoppingSubscriber),
+        ]);
+        let mut handler = EventHandler::new(vec![], JsonValue::Null, &input, subscribers);
+
+        handle_events(&mut handler, &text_events(&MessageId::random())).await;
+        assert_eq!(handler.messages[0].content(), Some("Hello!, world!"));
+
+        // The reported changes, default behavior included, rebuild the handler's messages
+        let mut messages = vec![];
+        let mut patcher = StatePatcher::new();
+        for change in changes.lock().unwrap().iter() {
+            change.apply(&mut messages, &mut JsonValue::Null, &mut patcher);
+        }
+        assert_eq!(messages, handler.messages);
+    }
+
+    #[derive(Default)]
+    struct ToolResultSubscriber {
+        buffers: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl AgentSubscriber for ToolResultSubscriber {
+        async fn on_tool_result_delta(
+            &self,
+            _delta: &ToolResultDelta,
+            tool_result_buffer: &str,
+            _params: AgentSubscriberParams<'async_trait, JsonValue, JsonValue>,
+        ) -> Result<AgentStateMutation, AgentError> {
+            self.buffers
+                .lock()
+                .unwrap()
+                .push(tool_result_buffer.to_string());
+            Ok(AgentStateMutation::default())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_result_deltas_are_buffered() {
+        let input = input();
+        let subscriber = ToolResultSubscriber::default();
+        let buffers = subscriber.buffers.clone();
+        let mut handler = new_handler(&input, subscriber);
+
+        let tool_call_id = ToolCallId::random();
+        let events: Vec<Event> = vec![
+            ToolResultDelta::new(tool_call_id.clone(), "compiling")
+                .into_event()
+                .unwrap(),
+            ToolResultDelta::new(tool_call_id.clone(), "... done")
+                .into_event()
+                .unwrap(),
+            Event::Custom(CustomEvent::new(
+                ToolResultDelta::NAME,
+                serde_json::json!({ "unexpected": true }),
+            )),
+        ];
+        handle_events(&mut handler, &events).await;
+        assert_eq!(
+            *buffers.lock().unwrap(),
+            vec!["compiling".to_string(), "compiling... done".to_string()]
+        );
+
+        handler
+            .handle_event(&Event::ToolCallResult(ToolCallResultEvent {
+                base: BaseEvent {
+                    timestamp: None,
+                    raw_event: None,
+                },
+                message_id: MessageId::random(),
+                tool_call_id: tool_call_id.clone(),
+                content: "ok".to_string(),
+                role: Role::Tool,
+            }))
+            .await
+            .unwrap();
+        assert!(handler.tool_result_buffers.is_empty());
+    }
+
+    #[derive(Default)]
+    struct CitationSubscriber {
+        counts: Arc<Mutex<Vec<usize>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl AgentSubscriber for CitationSubscriber {
+        async fn on_citation(
+            &self,
+            _citation: &Citation,
+            message_citations: &[Citation],
+            _params: AgentSubscriberParams<'async_trait, JsonValue, JsonValue>,
+        ) -> Result<AgentStateMutation, AgentError> {
+            self.counts.lock().unwrap().push(message_citations.len());
+            Ok(AgentStateMutation::default())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_citations_are_accumulated_per_message() {
+        let input = input();
+        let subscriber = CitationSubscriber::default();
+        let counts = subscriber.counts.clone();
+        let mut handler = new_handler(&input, subscriber);
+
+        let first = MessageId::random();
+        let second = MessageId::random();
+        for message_id in [&first, &first, &second] {
+            let event = Citation::new(message_id.clone(), 0..5, JsonValue::Null)
+                .into_event()
+                .unwrap();
+            handler.handle_event(&event).await.unwrap();
+        }
+
+        assert_eq!(*counts.lock().unwrap(), vec![1, 2, 1]);
+        assert_eq!(handler.citations[&first].len(), 2);
+    }
+
+    #[derive(Default)]
+    struct RevisionSubscriber {
+        /// The replaced and revised content of each revision
+        revisions: Arc<Mutex<Vec<(String, String)>>>,
+        changes: Arc<Mutex<Vec<AgentChange>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl AgentSubscriber for RevisionSubscriber {
+        async fn on_message_revision(
+            &self,
+            revision: &MessageRevision,
+            previous_content: &str,
+            params: AgentSubscriberParams<'async_trait, JsonValue, JsonValue>,
+        ) -> Result<AgentStateMutation, AgentError> {
+            let message = params
+                .messages
+                .iter()
+                .find(|m| *m.id() == revision.message_id)
+                .unwrap();
+            self.revisions.lock().unwrap().push((
+                previous_content.to_string(),
+                message.content().unwrap().to_string(),
+            ));
+            Ok(AgentStateMutation::default())
+        }
+
+        async fn on_changes(
+            &self,
+            changes: &[AgentChange],
+            _params: AgentSubscriberParams<'async_trait, JsonValue, JsonValue>,
+        ) -> Result<(), AgentError> {
+            self.changes.lock().unwrap().extend_from_slice(changes);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_message_revisions_are_applied() {
+        let input = input();
+        let subscriber = RevisionSubscriber::default();
+        let revisions = subscriber.revisions.clone();
+        let changes = subscriber.changes.clone();
+        let mut handler = new_handler(&input, subscriber);
+
+        let message_id = MessageId::random();
+        let mut events = text_events(&message_id);
+        events.push(
+            MessageRevision::edit(message_id.clone(), vec![TextEdit::new(7..12, "there")])
+                .into_event()
+                .unwrap(),
+        );
+        // Edits outside of the content are ignored
+        events.push(
+            MessageRevision::edit(message_id.clone(), vec![TextEdit::new(20..21, "!")])
+                .into_event()
+                .unwrap(),
+        );
+        events.push(
+            MessageRevision::replace(message_id.clone(), "Bye")
+                .into_event()
+                .unwrap(),
+        );
+        handle_events(&mut handler, &events).await;
+
+        assert_eq!(handler.message(&message_id).unwrap().content(), Some("Bye"));
+        assert_eq!(
+            *revisions.lock().unwrap(),
+            vec![
+                ("Hello, world".to_string(), "Hello, there".to_string()),
+                ("Hello, there".to_string(), "Bye".to_string()),
+            ]
+        );
+        let revised = changes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|change| matches!(change, AgentChange::ReviseContent { .. }))
+            .count();
+        assert_eq!(revised, 2);
+
+        let unknown = MessageRevision::replace(MessageId::random(), "?")
+            .into_event()
+            .unwrap();
+        assert!(matches!(
+            handler.handle_event(&unknown).await,
+            Err(AgentError::MisattributedDelta { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_message_revisions_move_citations() {
+        let input = input();
+        let mut handler = new_handler(&input, CitationSubscriber::default());
+
+        let message_id = MessageId::random();
+        let mut events = text_events(&message_id);
+        for range in [0..5, 7..12] {
+            events.push(
+                Citation::new(message_id.clone(), range, JsonValue::Null)
+                    .into_event()
+                    .unwrap(),
+            );
+        }
+        // Drops the citation of "world" and shifts that of "Hello"
+        events.push(
+            MessageRevision::edit(
+                message_id.clone(),
+                vec![TextEdit::new(7..12, "there"), TextEdit::new(0..0, "Oh, ")],
+            )
+            .into_event()
+            .unwrap(),
+        );
+        handle_events(&mut handler, &events).await;
+
+        let content = handler.message(&message_id).unwrap().content().unwrap();
+        let citations = &handler.citations[&message_id];
+        assert_eq!(content, "Oh, Hello, there");
+        assert_eq!(citations.len(), 1);
+        assert_eq!(citations[0].range(), 4..9);
+        assert_eq!(citations[0].cited_text(content), Some("Hello"));
+
+        let replace = MessageRevision::replace(message_id.clone(), "Bye")
+            .into_event()
+            .unwrap();
+        handler.handle_event(&replace).await.unwrap();
+        assert!(handler.citations[&message_id].is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_messages_delta_is_applied() {
+        let input = input();
+        let subscriber = RecordingSubscriber::default();
+        let messages_changed = subscriber.messages_changed.clone();
+        let changes = subscriber.changes.clone();
+        let user = Message::new_user("Hello");
+        let mut handler = EventHandler::new(
+            vec![user.clone()],
+            JsonValue::Null,
+            &input,
+            Subscribers::from_subscriber(subscriber),
+        );
+
+        let assistant = Message::new_assistant("Hi!");
+        let delta = MessagesDelta::new(
+            serde_json::from_value(serde_json::json!([
+                {"op": "replace", "path": "/0/content", "value": "Hello there"},
+                {"op": "add", "path": "/-", "value": assistant},
+            ]))
+            .unwrap(),
+        );
+        let mutation = handler
+            .handle_event(&delta.into_event().unwrap())
+            .await
+            .unwrap();
+        handler.apply_mutation(mutation).await.unwrap();
+
+        assert_eq!(*messages_changed.lock().unwrap(), 1);
+        // Only the messages touched are replaced
+        assert!(matches!(
+            changes.lock().unwrap().as_slice(),
+            [
+                AgentChange::Replace { index: 0, .. },
+                AgentChange::Insert { index: 1, .. }
+            ]
+        ));
+        assert_eq!(handler.messages.len(), 2);
+        assert_eq!(handler.messages[0].id(), user.id());
+        assert_eq!(handler.messages[0].content(), Some("Hello there"));
+        assert_eq!(handler.messages[1], assistant);
+
+        // Deltas that are malformed or fail to apply both fail the run, leaving the messages
+        let failing = MessagesDelta::new(
+            serde_json::from_value(serde_json::json!([
+                {"op": "replace", "path": "/1/content", "value": "Bye"},
+                {"op": "remove", "path": "/5"},
+            ]))
+            .unwrap(),
+        );
+        let malformed = CustomEvent::new(MessagesDelta::NAME, serde_json::json!({"delta": {}}));
+        for event in [failing.into_event().unwrap(), Event::Custom(malformed)] {
+            assert!(matches!(
+                handler.handle_event(&event).await,
+                Err(AgentError::Execution { .. })
+            ));
+        }
+        assert_eq!(handler.messages[1], assistant);
+    }
+
+    #[tokio::test]
+    async fn test_state_deltas_are_applied() {
+        let input = input();
+        let subscriber = RecordingSubscriber::default();
+        let changes = subscriber.changes.clone();
+        let mut handler = EventHandler::new(
+            vec![],
+            serde_json::json!({"count": 0}),
+            &input,
+            Subscribers::from_subscriber(subscriber),
+        );
+        let base = BaseEvent {
+            timestamp: None,
+            raw_event: None,
+        };
+        let delta = |patch: JsonValue| {
+            Event::StateDelta(StateDeltaEvent {
+                base: base.clone(),
+                delta: serde_json::from_value(patch).unwrap(),
+            })
+        };
+
+        let events = [
+            delta(serde_json::json!([{"op": "replace", "path": "/count", "value": 1}])),
+            delta(serde_json::json!([{"op": "add", "path": "/items", "value": ["a"]}])),
+            Event::StateSnapshot(StateSnapshotEvent {
+                base: base.clone(),
+                snapshot: serde_json::json!({"count": 10}),
+            }),
+            delta(serde_json::json!([{"op": "replace", "path": "/count", "value": 11}])),
+        ];
+        handle_events(&mut handler, &events).await;
+        assert_eq!(handler.state, serde_json::json!({"count": 11}));
+
+        // A failing patch leaves the state as it was
+        let failing = delta(serde_json::json!([
+            {"op": "replace", "path": "/count", "value": 12},
+            {"op": "remove", "path": "/missing"},
+        ]));
+        assert!(matches!(
+            handler.handle_event(&failing).await,
+            Err(AgentError::Execution { .. })
+        ));
+        let event = delta(serde_json::json!([{"op": "add", "path": "/done", "value": true}]));
+        let mutation = handler.handle_event(&event).await.unwrap();
+        handler.apply_mutation(mutation).await.unwrap();
+        assert_eq!(
+            handler.state,
+            serde_json::json!({"count": 11, "done": true})
+        );
+
+        // Deltas are reported as the patches themselves, which replay to the same state
+        let changes = changes.lock().unwrap();
+        assert!(matches!(
+            changes.as_slice(),
+            [
+                ..,
+                AgentChange::SetState(_),
+                AgentChange::PatchState(_),
+                AgentChange::PatchState(_)
+            ]
+        ));
+        let mut state = serde_json::json!({"count": 0});
+        let mut patcher = StatePatcher::new();
+        for change in changes.iter() {
+            change.apply(&mut vec![], &mut state, &mut patcher);
+        }
+        assert_eq!(state, handler.state);
+    }
+
+    #[derive(Default)]
+    struct ArgsSubscriber {
+        partial_args: Arc<Mutex<Vec<HashMap<String, JsonValue>>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl AgentSubscriber for ArgsSubscriber {
+        async fn on_tool_call_args_event(
+            &self,
+            _event: &ToolCallArgsEvent,
+            _tool_call_buffer: &str,
+            _tool_call_name: &str,
+            partial_tool_call_args: &HashMap<String, JsonValue>,
+            _params: AgentSubscriberParams<'async_trait, JsonValue, JsonValue>,
+        ) -> Result<AgentStateMutation, AgentError> {
+            self.partial_args
+                .lock()
+                .unwrap()
+                .push(partial_tool_call_args.clone());
+            Ok(AgentStateMutation::default())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partial_tool_call_args() {
+        let input = input();
+        let subscriber = ArgsSubscriber::default();
+        let partial_args = subscriber.partial_args.clone();
+        let mut handler = new_handler(&input, subscriber);
+
+        let call = ToolCallId::random();
+        let mut events = vec![Event::ToolCallStart(ToolCallStartEvent::new(
+            call.clone(),
+            "search",
+        ))];
+        for delta in ["{\"query\":\"ru", "st\",\"lim", "it\":5}"] {
+            events.push(Event::ToolCallArgs(ToolCallArgsEvent::new(
+                call.clone(),
+                delta,
+            )));
+        }
+        handle_events(&mut handler, &events).await;
+
+        let query = |args: &HashMap<String, JsonValue>| args["query"].clone();
+        let partial_args = partial_args.lock().unwrap();
+        assert_eq!(query(&partial_args[0]), "ru");
+        assert_eq!(query(&partial_args[1]), "rust");
+        assert!(!partial_args[1].contains_key("limit"));
+        assert_eq!(partial_args[2]["limit"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_interleaved_messages_and_tool_calls() {
+        let input = input();
+        let mut handler =
+            EventHandler::new(vec![], JsonValue::Null, &input, Subscribers::new(vec![]));
+
+        let first = MessageId::random();
+        let second = MessageId::random();
+        let call_a = ToolCallId::random();
+        let call_b = ToolCallId::random();
+        let content = |id: &MessageId, delta: &str| {
+            Event::TextMessageContent(
+                TextMessageContentEvent::new(id.clone(), delta.to_string()).unwrap(),
+            )
+        };
+        let args = |id: &ToolCallId, delta: &str| {
+            Event::ToolCallArgs(ToolCallArgsEvent::new(id.clone(), delta))
+        };
+        let events = vec![
+            Event::TextMessageStart(TextMessageStartEvent::new(first.clone())),
+            Event::TextMessageStart(TextMessageStartEvent::new(second.clone())),
+            content(&first, "one"),
+            Event::ToolCallStart(
+                ToolCallStartEvent::new(call_a.clone(), "a").with_parent_message_id(first.clone()),
+            ),
+            content(&second, "two"),
+            Event::ToolCallStart(ToolCallStartEvent::new(call_b.clone(), "b")),
+            args(&call_a, "{\"x\":"),
+            args(&call_b, "{}"),
+            args(&call_a, "1}"),
+        ];
+        handle_events(&mut handler, &events).await;
+
+        assert_eq!(handler.message(&first).unwrap().content(), Some("one"));
+        assert_eq!(handler.message(&second).unwrap().content(), Some("two"));
+        let tool_calls = handler.message(&first).unwrap().tool_calls().unwrap();
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].function.arguments, "{\"x\":1}");
+        // A tool call without a parent starts its own message
+        assert_eq!(handler.messages.len(), 3);
+        assert_eq!(handler.tool_call(&call_b).unwrap().function.arguments, "{}");
+    }
+
+    #[tokio::test]
+    async fn test_misattributed_deltas() {
+        let input = input();
+        let mut handler =
+            EventHandler::new(vec![], JsonValue::Null, &input, Subscribers::new(vec![]));
+        let message_id = MessageId::random();
+        let start = Event::TextMessageStart(TextMessageStartEvent::new(message_id));
+        let mutation = handler.handle_event(&start).await.unwrap();
+        handler.apply_mutation(mutation).await.unwrap();
+
+        let content = Event::TextMessageContent(
+            TextMessageContentEvent::new(MessageId::random(), "stray".to_string()).unwrap(),
+        );
+        assert!(matches!(
+            handler.handle_event(&content).await,
+            Err(AgentError::MisattributedDelta {
+                event_type: EventType::TextMessageContent,
+                ..
+            })
+        ));
+
+        let args = Event::ToolCallArgs(ToolCallArgsEvent::new(ToolCallId::random(), "{}"));
+        assert!(matches!(
+            handler.handle_event(&args).await,
+            Err(AgentError::MisattributedDelta {
+                event_type: EventType::ToolCallArgs,
+                ..
+            })
+        ));
+        assert_eq!(handler.messages[0].content(), Some(""));
+    }
+
+    #[tokio::test]
+    async fn test_messages_with_custom_role() {
+        let input = input();
+        let raw = serde_json::json!({
+            "type": "MESSAGES_SNAPSHOT",
+            "messages": [
+                {"id": MessageId::random(), "role": "user", "content": "Review this"},
+                {"id": MessageId::random(), "role": "critic", "content": "Looks fine", "score": 1}
+            ]
+        });
+        let event: Event = serde_json::from_value(raw.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&event).unwrap(), raw);
+        let Event::MessagesSnapshot(snapshot) = &event else {
+            panic!("Expected messages snapshot, got {event:?}");
+        };
+        let mut handler = EventHandler::new(
+            snapshot.messages.clone(),
+            JsonValue::Null,
+            &input,
+            Subscribers::new(vec![]),
+        );
+
+        let events = text_events(snapshot.messages[1].id());
+        // Deltas may target a message with a custom role taken from the history
+        let mutation = handler.handle_event(&events[1]).await.unwrap();
+        handler.apply_mutation(mutation).await.unwrap();
+        assert_eq!(
+            handler.messages[1].role(),
+            Role::Other("critic".to_string())
+        );
+        assert_eq!(handler.messages[1].content(), Some("Looks fineHello"));
+        let mut expected = raw["messages"].clone();
+        expected[1]["content"] = "Looks fineHello".into();
+        assert_eq!(serde_json::to_value(&handler.messages).unwrap(), expected);
+    }
+}
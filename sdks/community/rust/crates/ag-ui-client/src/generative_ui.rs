@@ -0,0 +1,134 @@
+//! Extraction of the components an agent asks the client to render, see
+//! [`ag_ui_core::generative_ui`].
+
+use std::marker::PhantomData;
+
+use futures::channel::mpsc;
+use log::warn;
+
+use crate::agent::{AgentError, AgentStateMutation};
+use crate::core::event::ToolCallEndEvent;
+use crate::core::generative_ui::UiComponent;
+use crate::core::types::{FunctionCall, MessageId, ToolCall, ToolCallId};
+use crate::core::{AgentState, FwdProps, JsonValue};
+use crate::subscriber::{AgentSubscriber, AgentSubscriberParams};
+use std::collections::HashMap;
+
+/// A request of the agent to render a component.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderIntent<T> {
+    pub tool_call_id: ToolCallId,
+    pub parent_message_id: Option<MessageId>,
+    pub props: T,
+}
+
+/// Subscriber extracting the [`RenderIntent`]s of a component from the tool calls of a run.
+///
+/// Intents are sent as soon as the arguments of the tool call are complete. Tool calls whose
+/// arguments do not match the props of the component are logged and skipped.
+pub struct RenderIntents<T> {
+    intents: mpsc::UnboundedSender<RenderIntent<T>>,
+    _component: PhantomData<fn() -> T>,
+}
+
+impl<T: UiComponent> RenderIntents<T> {
+    /// Creates the subscriber, along with a receiver of the intents as they are extracted.
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<RenderIntent<T>>) {
+        let (intents, receiver) = mpsc::unbounded();
+        let subscriber = Self {
+            intents,
+            _component: PhantomData,
+        };
+        (subscriber, receiver)
+    }
+}
+
+#[async_trait::async_trait]
+impl<StateT, FwdPropsT, T> AgentSubscriber<StateT, FwdPropsT> for RenderIntents<T>
+where
+    StateT: AgentState,
+    FwdPropsT: FwdProps,
+    T: UiComponent + Send + 'static,
+{
+    async fn on_tool_call_end_event(
+        &self,
+        event: &ToolCallEndEvent,
+        _tool_call_name: &str,
+        _tool_call_args: &HashMap<String, JsonValue>,
+        params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<AgentStateMutation<StateT>, AgentError> {
+        // The tracker knows the tool call even before it is attached to a message
+        let Some(tracked) = params.tool_calls.get(&event.tool_call_id) else {
+            return Ok(AgentStateMutation::default());
+        };
+        if tracked.name != T::NAME {
+            return Ok(AgentStateMutation::default());
+        }
+
+        let tool_call = ToolCall::new(
+            tracked.id,
+            FunctionCall {
+                name: tracked.name,
+                arguments: tracked.arguments,
+            },
+        );
+        match T::from_tool_call(&tool_call) {
+            Some(Ok(props)) => {
+                // Nobody listening for intents is not an error
+                let _ = self.intents.unbounded_send(RenderIntent {
+                    tool_call_id: tool_call.id,
+                    parent_message_id: tracked.parent_message_id,
+                    props,
+                });
+            }
+            Some(Err(err)) => warn!("Ignoring {} with invalid props: {err}", T::NAME),
+            None => {}
+        }
+        Ok(AgentStateMutation::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RunAgentParams;
+    use crate::core::event::Event;
+    use crate::event_handler::testing::{handle_events, input_from, new_handler};
+    use futures::StreamExt;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct ItemsList {
+        items: Vec<String>,
+    }
+
+    impl UiComponent for ItemsList {
+        const NAME: &'static str = "render_ItemsList";
+        const DESCRIPTION: &'static str = "Shows a list of items";
+
+        fn parameters() -> JsonValue {
+            serde_json::json!({
+                "type": "object",
+                "properties": {"items": {"type": "array", "items": {"type": "string"}}}
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_render_intents_are_extracted() {
+        let input = input_from(RunAgentParams::new().add_tool(ItemsList::tool()));
+        let (subscriber, mut intents) = RenderIntents::<ItemsList>::new();
+        let mut handler = new_handler(&input, subscriber);
+
+        let props = ItemsList {
+            items: vec!["apples".to_string(), "pears".to_string()],
+        };
+        let events: Vec<Event> = props.clone().into_events(None).unwrap();
+        handle_events(&mut handler, &events).await;
+        drop(handler);
+
+        let intent = intents.next().await.unwrap();
+        assert_eq!(intent.props, props);
+        assert!(intents.next().await.is_none());
+    }
+}
@@ -2,23 +2,252 @@ use crate::Agent;
 use crate::agent::AgentError;
 use crate::core::event::Event;
 use crate::core::types::RunAgentInput;
-use crate::core::{AgentState, FwdProps};
-use crate::sse::SseResponseExt;
+use crate::core::{AgentState, FwdProps, JsonValue};
+use crate::retry::{RetryPolicy, with_retry_resuming};
+use crate::sse::{SseEvent, SseFrame, SseResponseExt};
 use crate::stream::EventStream;
 use ag_ui_core::types::AgentId;
 use async_trait::async_trait;
+use bytes::Bytes;
 use futures::StreamExt;
-use log::{debug, trace};
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use reqwest::{Client as HttpClient, Url};
+use futures::stream::BoxStream;
+use log::{debug, trace, warn};
+use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client as HttpClient, Response, Url};
+use std::marker::PhantomData;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Maximum number of characters of an offending payload included in [`AgentError::InvalidFrame`]
+const INVALID_FRAME_SNIPPET_LEN: usize = 256;
+
+/// How an [`HttpAgent`] treats SSE frames whose data cannot be decoded into an event.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameErrorPolicy {
+    /// Fail the run with [`AgentError::InvalidFrame`]
+    #[default]
+    Fail,
+    /// Log a warning and continue with the next frame
+    Skip,
+}
+
+/// Header carrying the ID of the last received SSE frame when reconnecting
+const LAST_EVENT_ID: &str = "Last-Event-ID";
+
+/// How an [`HttpAgent`] re-establishes an event stream whose connection dropped mid-run.
+///
+/// Set with [`HttpAgentBuilder::with_reconnect_policy`].
+pub type ReconnectPolicy = RetryPolicy;
+
+/// Callback invoked with every [`AgentError::InvalidFrame`], regardless of the
+/// [`FrameErrorPolicy`]. Useful for reporting dropped frames to metrics.
+pub type InvalidFrameHook = Arc<dyn Fn(&AgentError) + Send + Sync>;
+
+/// Callback invoked with every SSE frame without data, such as the heartbeat comments servers
+/// send to keep the connection alive. Useful for observing the health of the connection.
+pub type HeartbeatHook = Arc<dyn Fn(&SseEvent) + Send + Sync>;
+
+/// Decodes the data of SSE frames into events according to the configured policy
+#[derive(Clone, Default)]
+struct FrameDecoder {
+    policy: FrameErrorPolicy,
+    hook: Option<InvalidFrameHook>,
+    heartbeat_hook: Option<HeartbeatHook>,
+    tolerate_unknown: bool,
+}
+
+impl FrameDecoder {
+    /// Decodes the event carried by an SSE frame.
+    ///
+    /// Returns `None` for frames that do not yield an event: frames without data (such as
+    /// comments) and, under [`FrameErrorPolicy::Skip`], frames that fail to decode.
+    fn decode<StateT: AgentState>(
+        &self,
+        sse_event: &SseEvent,
+    ) -> Option<Result<Event<StateT>, AgentError>> {
+        if sse_event.data.is_empty() {
+            trace!("Ignoring frame without data at offset {}", sse_event.offset);
+            if let Some(hook) = &self.heartbeat_hook {
+                hook(sse_event);
+            }
+            return None;
+        }
+
+        let parsed = match serde_json::from_str::<Event<StateT>>(&sse_event.data) {
+            // Retry in compatibility mode, keeping the original error for malformed events
+            Err(err) if self.tolerate_unknown => serde_json::from_str(&sse_event.data)
+                .and_then(Event::from_value_compat)
+                .map_err(|_| err),
+            parsed => parsed,
+        };
+
+        match parsed {
+            Ok(event) => {
+                debug!("Deserialized event: {event:?}");
+                Some(Ok(event))
+            }
+            Err(source) => {
+                let err = AgentError::InvalidFrame {
+                    offset: sse_event.offset,
+                    snippet: sse_event
+                        .data
+                        .chars()
+                        .take(INVALID_FRAME_SNIPPET_LEN)
+                        .collect(),
+                    source,
+                };
+                if let Some(hook) = &self.hook {
+                    hook(&err);
+                }
+                match self.policy {
+                    FrameErrorPolicy::Fail => Some(Err(err)),
+                    FrameErrorPolicy::Skip => {
+                        warn!("Skipping frame: {err}");
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// An event paired with the raw SSE frame it was decoded from.
+///
+/// Produced by [`HttpAgent::run_with_frames`].
+#[derive(Debug, Clone)]
+pub struct FramedEvent<StateT: AgentState = JsonValue> {
+    /// The SSE frame as received on the wire
+    pub frame: SseFrame,
+    /// The event decoded from the frame's data
+    pub event: Event<StateT>,
+}
+
+/// Fails a stream with [`AgentError::Stalled`] once no item arrives within `max_gap`
+fn detect_stalls<T: Send + 'static>(
+    stream: BoxStream<'static, Result<T, AgentError>>,
+    max_gap: Duration,
+) -> BoxStream<'static, Result<T, AgentError>> {
+    futures::stream::unfold(Some(stream), move |stream| async move {
+        let mut stream = stream?;
+        match tokio::time::timeout(max_gap, stream.next()).await {
+            Ok(Some(item)) => Some((item, Some(stream))),
+            Ok(None) => None,
+            // End the stream after reporting the stall
+            Err(_) => Some((Err(AgentError::Stalled { max_gap }), None)),
+        }
+    })
+    .boxed()
+}
+
+/// Opens the event stream of a run. Detached from the [`HttpAgent`], so that a dropped stream can
+/// be reopened after the run has started.
+#[derive(Clone)]
+struct EventSource {
+    http_client: HttpClient,
+    base_url: Url,
+    header_map: HeaderMap,
+    decoder: FrameDecoder,
+    max_event_gap: Option<Duration>,
+    /// ID of the last SSE frame received, sent as `Last-Event-ID` when reopening the stream
+    last_event_id: Arc<Mutex<Option<String>>>,
+}
+
+impl EventSource {
+    /// Sends the run request, surfacing non-success statuses as errors
+    async fn send(&self, body: Bytes) -> Result<Response, AgentError> {
+        let mut request = self
+            .http_client
+            .post(self.base_url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .headers(self.header_map.clone())
+            .body(body);
+        if let Some(id) = self.last_event_id.lock().unwrap().as_deref() {
+            debug!("Resuming event stream after event {id}");
+            request = request.header(LAST_EVENT_ID, id);
+        }
+
+        // Send the request and get the response
+        let response = request.send().await?;
+
+        // Check HTTP status and surface structured error on non-success
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            let snippet: String = text.chars().take(512).collect();
+            return Err(AgentError::HttpStatus {
+                status,
+                context: snippet,
+            });
+        }
+
+        Ok(response)
+    }
+
+    /// Applies the stall detection configured with [`HttpAgentBuilder::with_max_event_gap`] to a
+    /// stream of SSE frames
+    fn watch_stalls<T: Send + 'static>(
+        &self,
+        stream: BoxStream<'static, Result<T, AgentError>>,
+    ) -> BoxStream<'static, Result<T, AgentError>> {
+        match self.max_event_gap {
+            Some(max_gap) => detect_stalls(stream, max_gap),
+            None => stream,
+        }
+    }
+
+    /// Sends the run request and decodes the response into a stream of events
+    async fn open<StateT: AgentState>(
+        &self,
+        body: Bytes,
+    ) -> Result<EventStream<'static, StateT>, AgentError> {
+        let response = self.send(body).await?;
+        let decoder = self.decoder.clone();
+        let last_event_id = self.last_event_id.clone();
+
+        // Convert the response to an SSE event stream
+        let stream = self
+            .watch_stalls(response.event_source().await)
+            .filter_map(move |result| {
+                let item = match result {
+                    Ok(event) => {
+                        trace!("Received event: {event:?}");
+                        if let Some(id) = &event.id {
+                            *last_event_id.lock().unwrap() = Some(id.clone());
+                        }
+                        decoder.decode(&event)
+                    }
+                    Err(err) => Some(Err(err)),
+                };
+                futures::future::ready(item)
+            })
+            .boxed();
+        Ok(stream)
+    }
+}
+
+/// Marker for an [`HttpAgent`] that runs with any state and forwarded props types, which are
+/// then inferred from the run parameters and subscribers.
+pub struct Untyped;
+
+/// Marker for an [`HttpAgent`] bound to one state and forwarded props type.
+///
+/// Created with [`HttpAgentBuilder::typed`].
+pub struct Typed<StateT, FwdPropsT>(PhantomData<fn() -> (StateT, FwdPropsT)>);
 
 /// Represents an agent that communicates primarily via HTTP.
-pub struct HttpAgent {
+///
+/// By default the agent implements [`Agent`] for every state and forwarded props type. Use
+/// [`HttpAgentBuilder::typed`] to bind it to specific types instead.
+pub struct HttpAgent<TypesT = Untyped> {
     http_client: HttpClient,
     base_url: Url,
     header_map: HeaderMap,
     agent_id: Option<AgentId>,
+    decoder: FrameDecoder,
+    max_event_gap: Option<Duration>,
+    reconnect_policy: Option<ReconnectPolicy>,
+    types: PhantomData<TypesT>,
 }
 
 impl HttpAgent {
@@ -32,6 +261,10 @@ impl HttpAgent {
             base_url,
             header_map,
             agent_id: None,
+            decoder: FrameDecoder::default(),
+            max_event_gap: None,
+            reconnect_policy: None,
+            types: PhantomData,
         }
     }
 
@@ -40,11 +273,106 @@ impl HttpAgent {
     }
 }
 
-pub struct HttpAgentBuilder {
+impl<TypesT> HttpAgent<TypesT> {
+    /// Runs the agent like [`Agent::run`], but pairs every decoded event with the raw SSE frame
+    /// it was decoded from. Useful for debugging and byte-accurate logging of the wire format.
+    ///
+    /// The raw frames are only retained on this code path; [`Agent::run`] does not pay for them.
+    ///
+    /// Unlike [`Agent::run`], the stream is never reopened: the [`ReconnectPolicy`] set with
+    /// [`HttpAgentBuilder::with_reconnect_policy`] does not apply, and a dropped connection or a
+    /// stall ends the stream with its error. The frames are those of the one response, so a
+    /// caller wanting to resume can send the last `frame.event.id` as `Last-Event-ID` itself.
+    pub async fn run_with_frames<StateT, FwdPropsT>(
+        &self,
+        input: &RunAgentInput<StateT, FwdPropsT>,
+    ) -> Result<BoxStream<'static, Result<FramedEvent<StateT>, AgentError>>, AgentError>
+    where
+        StateT: AgentState,
+        FwdPropsT: FwdProps,
+    {
+        let source = self.event_source();
+        let response = source.send(serde_json::to_vec(input)?.into()).await?;
+        let decoder = self.decoder.clone();
+
+        let stream = source
+            .watch_stalls(response.frame_source().await)
+            .filter_map(move |result| {
+                let item = match result {
+                    Ok(frame) => {
+                        trace!("Received frame: {:?}", frame.raw);
+                        decoder
+                            .decode(&frame.event)
+                            .map(|event| event.map(|event| FramedEvent { frame, event }))
+                    }
+                    Err(err) => Some(Err(err)),
+                };
+                futures::future::ready(item)
+            })
+            .boxed();
+        Ok(stream)
+    }
+
+    /// Detaches the state needed to open the event stream of a run
+    fn event_source(&self) -> EventSource {
+        EventSource {
+            http_client: self.http_client.clone(),
+            base_url: self.base_url.clone(),
+            header_map: self.header_map.clone(),
+            decoder: self.decoder.clone(),
+            max_event_gap: self.max_event_gap,
+            last_event_id: Arc::default(),
+        }
+    }
+
+    /// Sends the run request and decodes the response into a stream of events, reconnecting
+    /// according to the [`ReconnectPolicy`] if one is set
+    async fn event_stream<StateT, FwdPropsT>(
+        &self,
+        input: &RunAgentInput<StateT, FwdPropsT>,
+    ) -> Result<EventStream<'static, StateT>, AgentError>
+    where
+        StateT: AgentState,
+        FwdPropsT: FwdProps,
+    {
+        let source = self.event_source();
+        let body: Bytes = serde_json::to_vec(input)?.into();
+        let stream = source.open(body.clone()).await?;
+        let Some(policy) = self.reconnect_policy.clone() else {
+            return Ok(stream);
+        };
+
+        // The first connection is made eagerly so that failing to start the run is reported
+        // as before; only the connections replacing dropped ones go through the retries
+        let mut first = Some(stream);
+        let last_event_id = source.last_event_id.clone();
+        let reconnect = move || {
+            let first = first.take();
+            let source = source.clone();
+            let body = body.clone();
+            async move {
+                match first {
+                    Some(stream) => Ok(stream),
+                    None => source.open(body).await,
+                }
+            }
+        };
+        // Once the server assigns event ids, reconnections resume after the last one, so there
+        // are no replayed events to drop
+        let resumes = move || last_event_id.lock().unwrap().is_some();
+        Ok(with_retry_resuming(reconnect, policy, resumes))
+    }
+}
+
+pub struct HttpAgentBuilder<TypesT = Untyped> {
     base_url: Option<Url>,
     header_map: HeaderMap,
     http_client: Option<HttpClient>,
     agent_id: Option<AgentId>,
+    decoder: FrameDecoder,
+    max_event_gap: Option<Duration>,
+    reconnect_policy: Option<ReconnectPolicy>,
+    types: PhantomData<TypesT>,
 }
 
 impl HttpAgentBuilder {
@@ -54,6 +382,81 @@ impl HttpAgentBuilder {
             header_map: HeaderMap::new(),
             http_client: None,
             agent_id: None,
+            decoder: FrameDecoder::default(),
+            max_event_gap: None,
+            reconnect_policy: None,
+            types: PhantomData,
+        }
+    }
+}
+
+impl<TypesT> HttpAgentBuilder<TypesT> {
+    /// Binds the agent to a state and forwarded props type, so that they are inferred for runs
+    /// rather than having to be spelled out at every call site.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use ag_ui_client::{Agent, HttpAgent, RunAgentParams};
+    /// # use ag_ui_client::core::AgentState;
+    /// # use serde::{Deserialize, Serialize};
+    /// #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+    /// struct Counter {
+    ///     count: u32,
+    /// }
+    /// impl AgentState for Counter {}
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let agent = HttpAgent::builder()
+    ///     .with_url_str("http://127.0.0.1:3000/")?
+    ///     .typed::<Counter, ()>()
+    ///     .build()?;
+    ///
+    /// let result = agent.run_agent(&RunAgentParams::new_typed(), ()).await?;
+    /// println!("{}", result.new_state.count);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Without it, nothing ties the parameters to a state type when no typed subscriber is
+    /// passed, and the run fails to compile with "type annotations needed":
+    /// ```compile_fail,E0283
+    /// # use ag_ui_client::{Agent, HttpAgent, RunAgentParams};
+    /// # async fn run(agent: HttpAgent) -> Result<(), Box<dyn std::error::Error>> {
+    /// let result = agent.run_agent(&RunAgentParams::new_typed(), ()).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// A typed agent also rejects parameters of another state type:
+    /// ```compile_fail,E0308
+    /// # use ag_ui_client::{Agent, HttpAgent, RunAgentParams};
+    /// # use ag_ui_client::http::Typed;
+    /// # use ag_ui_client::core::AgentState;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+    /// # struct Counter;
+    /// # impl AgentState for Counter {}
+    /// # async fn run(agent: HttpAgent<Typed<Counter, ()>>) -> Result<(), Box<dyn std::error::Error>> {
+    /// // `RunAgentParams::new` uses JSON values for the state and forwarded props
+    /// let result = agent.run_agent(&RunAgentParams::new(), ()).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn typed<StateT, FwdPropsT>(self) -> HttpAgentBuilder<Typed<StateT, FwdPropsT>>
+    where
+        StateT: AgentState,
+        FwdPropsT: FwdProps,
+    {
+        HttpAgentBuilder {
+            base_url: self.base_url,
+            header_map: self.header_map,
+            http_client: self.http_client,
+            agent_id: self.agent_id,
+            decoder: self.decoder,
+            max_event_gap: self.max_event_gap,
+            reconnect_policy: self.reconnect_policy,
+            types: PhantomData,
         }
     }
 
@@ -124,7 +527,59 @@ impl HttpAgentBuilder {
         self
     }
 
-    pub fn build(self) -> Result<HttpAgent, AgentError> {
+    /// Set how frames that cannot be decoded into an event are handled.
+    /// Defaults to [`FrameErrorPolicy::Fail`].
+    pub fn with_frame_error_policy(mut self, policy: FrameErrorPolicy) -> Self {
+        self.decoder.policy = policy;
+        self
+    }
+
+    /// Enable compatibility mode for event types this crate does not recognise.
+    ///
+    /// When enabled, such events are decoded into [`Event::Unknown`] and skipped with a warning
+    /// instead of being treated as invalid frames. Defaults to disabled.
+    pub fn with_unknown_events(mut self, tolerate: bool) -> Self {
+        self.decoder.tolerate_unknown = tolerate;
+        self
+    }
+
+    /// Set a callback invoked for every frame that cannot be decoded into an event
+    pub fn with_invalid_frame_hook(
+        mut self,
+        hook: impl Fn(&AgentError) + Send + Sync + 'static,
+    ) -> Self {
+        self.decoder.hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Set the maximum time to wait for the next SSE frame, after which the run fails with
+    /// [`AgentError::Stalled`]. Heartbeat comments count as frames, so servers sending them keep
+    /// slow runs alive. Disabled by default.
+    pub fn with_max_event_gap(mut self, max_gap: Duration) -> Self {
+        self.max_event_gap = Some(max_gap);
+        self
+    }
+
+    /// Reconnect event streams that fail with a retryable error, such as a connection dropped
+    /// mid-run, according to `policy`. Disabled by default.
+    ///
+    /// The ID of the last SSE frame received is sent in the `Last-Event-ID` header of the new
+    /// request, so that servers supporting it resume the stream. Events that a server without
+    /// event ids replays from the start are dropped, and every reconnection is announced with a
+    /// [`Retry`](crate::core::extensions::Retry) custom event; see
+    /// [`with_retry`](crate::retry::with_retry).
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = Some(policy);
+        self
+    }
+
+    /// Set a callback invoked for every frame without data, such as heartbeat comments
+    pub fn with_heartbeat_hook(mut self, hook: impl Fn(&SseEvent) + Send + Sync + 'static) -> Self {
+        self.decoder.heartbeat_hook = Some(Arc::new(hook));
+        self
+    }
+
+    pub fn build(self) -> Result<HttpAgent<TypesT>, AgentError> {
         let base_url = self.base_url.ok_or(AgentError::Config {
             message: "Base URL is required".to_string(),
         })?;
@@ -143,6 +598,10 @@ impl HttpAgentBuilder {
             base_url,
             header_map: self.header_map,
             agent_id: self.agent_id,
+            decoder: self.decoder,
+            max_event_gap: self.max_event_gap,
+            reconnect_policy: self.reconnect_policy,
+            types: PhantomData,
         })
     }
 }
@@ -163,46 +622,253 @@ where
         &self,
         input: &RunAgentInput<StateT, FwdPropsT>,
     ) -> Result<EventStream<'async_trait, StateT>, AgentError> {
-        // Send the request and get the response
-        let response = self
-            .http_client
-            .post(self.base_url.clone())
-            .json(input)
-            .headers(self.header_map.clone())
-            .send()
-            .await?;
+        self.event_stream(input).await
+    }
 
-        // Check HTTP status and surface structured error on non-success
-        let status = response.status();
-        if !status.is_success() {
-            let text = response.text().await.unwrap_or_default();
-            let snippet: String = text.chars().take(512).collect();
-            return Err(AgentError::HttpStatus {
-                status,
-                context: snippet,
-            });
+    fn agent_id(&self) -> Option<&AgentId> {
+        self.agent_id.as_ref()
+    }
+}
+
+#[async_trait]
+impl<StateT, FwdPropsT> Agent<StateT, FwdPropsT> for HttpAgent<Typed<StateT, FwdPropsT>>
+where
+    StateT: AgentState,
+    FwdPropsT: FwdProps,
+{
+    async fn run(
+        &self,
+        input: &RunAgentInput<StateT, FwdPropsT>,
+    ) -> Result<EventStream<'async_trait, StateT>, AgentError> {
+        self.event_stream(input).await
+    }
+
+    fn agent_id(&self) -> Option<&AgentId> {
+        self.agent_id.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::{RunId, ThreadId};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn sse_event(data: &str) -> SseEvent {
+        SseEvent {
+            event: None,
+            id: None,
+            data: data.to_string(),
+            offset: 42,
         }
+    }
 
-        // Convert the response to an SSE event stream
-        let stream = response
-            .event_source()
-            .await
-            .map(|result| match result {
-                Ok(event) => {
-                    trace!("Received event: {event:?}");
+    #[test]
+    fn test_decoder_policies() {
+        let invalid = sse_event("{\"type\":\"NOT_AN_EVENT\"}");
+
+        let decoder = FrameDecoder::default();
+        match decoder.decode::<JsonValue>(&invalid) {
+            Some(Err(AgentError::InvalidFrame {
+                offset, snippet, ..
+            })) => {
+                assert_eq!(offset, 42);
+                assert_eq!(snippet, invalid.data);
+            }
+            other => panic!("Expected invalid frame error, got {other:?}"),
+        }
+
+        let hook_calls = Arc::new(AtomicUsize::new(0));
+        let counter = hook_calls.clone();
+        let decoder = FrameDecoder {
+            policy: FrameErrorPolicy::Skip,
+            hook: Some(Arc::new(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })),
+            heartbeat_hook: None,
+            tolerate_unknown: false,
+        };
+        assert!(decoder.decode::<JsonValue>(&invalid).is_none());
+        assert_eq!(hook_calls.load(Ordering::SeqCst), 1);
+
+        // Frames without data never yield an event
+        assert!(decoder.decode::<JsonValue>(&sse_event("")).is_none());
+
+        let valid = sse_event("{\"type\":\"STEP_STARTED\",\"stepName\":\"a\"}");
+        assert!(matches!(decoder.decode::<JsonValue>(&valid), Some(Ok(_))));
+    }
+
+    #[test]
+    fn test_unknown_events() {
+        let decoder = FrameDecoder {
+            tolerate_unknown: true,
+            ..Default::default()
+        };
+
+        let unknown = sse_event(r#"{"type":"NOT_AN_EVENT","value":1}"#);
+        match decoder.decode::<JsonValue>(&unknown) {
+            Some(Ok(Event::Unknown { type_name, payload })) => {
+                assert_eq!(type_name, "NOT_AN_EVENT");
+                assert_eq!(payload["value"], 1);
+            }
+            other => panic!("Expected unknown event, got {other:?}"),
+        }
+
+        // Malformed events of a known type are still invalid
+        let malformed = sse_event(r#"{"type":"STEP_STARTED","stepName":1}"#);
+        match decoder.decode::<JsonValue>(&malformed) {
+            Some(Err(AgentError::InvalidFrame { source, .. })) => {
+                assert!(source.to_string().contains("invalid type"))
+            }
+            other => panic!("Expected invalid frame error, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stall_detection() {
+        let frames = futures::stream::iter([Ok(sse_event(""))])
+            .chain(futures::stream::pending())
+            .boxed();
+        let mut stream = detect_stalls::<SseEvent>(frames, Duration::from_millis(10));
+
+        assert!(stream.next().await.unwrap().is_ok());
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(matches!(err, AgentError::Stalled { .. }));
+        assert!(err.is_retryable());
+        assert!(stream.next().await.is_none());
+    }
 
-                    let event_data: Event<StateT> = serde_json::from_str(&event.data)?;
-                    debug!("Deserialized event: {event_data:?}");
+    #[test]
+    fn test_heartbeat_hook() {
+        let heartbeats = Arc::new(AtomicUsize::new(0));
+        let counter = heartbeats.clone();
+        let decoder = FrameDecoder {
+            heartbeat_hook: Some(Arc::new(move |_: &SseEvent| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })),
+            ..FrameDecoder::default()
+        };
 
-                    Ok(event_data)
+        assert!(decoder.decode::<JsonValue>(&sse_event("")).is_none());
+        assert!(
+            decoder
+                .decode::<JsonValue>(&sse_event("{\"type\":\"NOT_AN_EVENT\"}"))
+                .is_some()
+        );
+        assert_eq!(heartbeats.load(Ordering::SeqCst), 1);
+    }
+
+    /// Serves each response in turn on a local port, returning the requests received
+    /// The length of an HTTP request, once its headers have been read
+    fn request_len(request: &[u8]) -> Option<usize> {
+        let headers_len = request.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+        let headers = std::str::from_utf8(&request[..headers_len]).unwrap();
+        let content_len = headers
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("content-length")
+                    .then(|| value.trim().parse::<usize>().unwrap())
+            })
+            .unwrap_or(0);
+        Some(headers_len + content_len)
+    }
+
+    async fn serve(responses: Vec<String>) -> (Url, tokio::task::JoinHandle<Vec<String>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                // Read the headers, then as many body bytes as their content length gives
+                let mut expected = None;
+                while expected.is_none_or(|len| request.len() < len) {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    assert!(n > 0, "connection closed before the end of the request");
+                    request.extend_from_slice(&buf[..n]);
+                    if expected.is_none() {
+                        expected = request_len(&request);
+                    }
                 }
-                Err(err) => Err(err),
+                requests.push(String::from_utf8(request).unwrap().to_lowercase());
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_with_last_event_id() {
+        let started = r#"{"type":"RUN_STARTED","threadId":"00000000-0000-0000-0000-00000000000b","runId":"00000000-0000-0000-0000-00000000000c"}"#;
+        let finished = r#"{"type":"RUN_FINISHED","threadId":"00000000-0000-0000-0000-00000000000b","runId":"00000000-0000-0000-0000-00000000000c"}"#;
+        // The first response is cut off in the middle of its chunked body
+        let frame = format!("id: 1\ndata: {started}\n\n");
+        let dropped = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ntransfer-encoding: chunked\r\n\r\n{:x}\r\n{frame}\r\n",
+            frame.len()
+        );
+        let frame = format!("id: 2\ndata: {finished}\n\n");
+        let resumed = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncontent-length: {}\r\n\r\n{frame}",
+            frame.len()
+        );
+        let (url, server) = serve(vec![dropped, resumed]).await;
+
+        let agent = HttpAgent::builder()
+            .with_url(url)
+            .with_reconnect_policy(ReconnectPolicy {
+                initial_backoff: Duration::ZERO,
+                ..ReconnectPolicy::default()
             })
-            .boxed();
-        Ok(stream)
+            .build()
+            .unwrap();
+        let input = RunAgentInput::<JsonValue, JsonValue>::new(
+            ThreadId::random(),
+            RunId::random(),
+            JsonValue::Null,
+            vec![],
+            vec![],
+            vec![],
+            JsonValue::Null,
+        );
+        let events: Vec<_> = agent.run(&input).await.unwrap().collect().await;
+
+        assert!(matches!(events[0], Ok(Event::RunStarted(_))));
+        assert!(matches!(&events[1], Ok(Event::Custom(e)) if e.name == "RETRY"));
+        assert!(matches!(events[2], Ok(Event::RunFinished(_))));
+        assert_eq!(events.len(), 3);
+
+        let requests = server.await.unwrap();
+        assert!(!requests[0].contains("last-event-id"));
+        assert!(requests[1].contains("last-event-id: 1\r\n"));
     }
 
-    fn agent_id(&self) -> Option<&AgentId> {
-        self.agent_id.as_ref()
+    #[test]
+    fn test_decode_valid_frames() {
+        let decoder = FrameDecoder::default();
+        let content = sse_event(
+            r#"{"type":"TEXT_MESSAGE_CONTENT","messageId":"00000000-0000-0000-0000-000000000000","delta":"Hi","timestamp":1.5}"#,
+        );
+        match decoder.decode::<JsonValue>(&content) {
+            Some(Ok(Event::TextMessageContent(e))) => {
+                assert_eq!(e.delta, "Hi");
+                assert_eq!(e.base.timestamp, Some(1.5));
+            }
+            other => panic!("Expected text message content, got {other:?}"),
+        }
+
+        let snapshot = sse_event(r#"{"type":"STATE_SNAPSHOT","snapshot":{"items":[1,2.5,"x"]}}"#);
+        match decoder.decode::<JsonValue>(&snapshot) {
+            Some(Ok(Event::StateSnapshot(e))) => {
+                assert_eq!(e.snapshot, serde_json::json!({"items": [1, 2.5, "x"]}))
+            }
+            other => panic!("Expected state snapshot, got {other:?}"),
+        }
     }
 }
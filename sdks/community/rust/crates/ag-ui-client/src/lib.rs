@@ -1,12 +1,21 @@
 #![doc = include_str!("../README.md")]
 
 pub mod agent;
+pub mod audio;
+pub mod buffered;
 pub mod error;
 pub mod event_handler;
+pub mod generative_ui;
 pub mod http;
+pub(crate) mod messages_delta;
+pub mod retry;
 pub mod sse;
+pub mod state_subscription;
+#[cfg(feature = "store")]
+pub mod store;
 pub(crate) mod stream;
 pub mod subscriber;
+pub mod tool_calls;
 pub use agent::{Agent, RunAgentParams};
 pub use http::HttpAgent;
 
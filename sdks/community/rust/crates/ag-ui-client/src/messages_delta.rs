@@ -0,0 +1,263 @@
+//! Application of `MESSAGES_DELTA` patches as changes to the messages they touch.
+
+use json_patch::jsonptr::{Pointer, PointerBuf};
+use json_patch::{
+    AddOperation, Patch, PatchOperation, RemoveOperation, ReplaceOperation, TestOperation,
+};
+use serde::de::DeserializeOwned;
+
+use crate::agent::AgentChange;
+use crate::core::JsonValue;
+use crate::core::types::Message;
+
+/// An operation of a patch, with moves and copies expanded into removals and additions
+enum Operation {
+    Add(PointerBuf, JsonValue),
+    Remove(PointerBuf),
+    Replace(PointerBuf, JsonValue),
+    Test(PointerBuf, JsonValue),
+}
+
+impl Operation {
+    fn path(&self) -> &Pointer {
+        match self {
+            Operation::Add(path, _)
+            | Operation::Remove(path)
+            | Operation::Replace(path, _)
+            | Operation::Test(path, _) => path,
+        }
+    }
+
+    /// The operation as a patch operation on `path`
+    fn at(&self, path: PointerBuf) -> PatchOperation {
+        match self {
+            Operation::Add(_, value) => PatchOperation::Add(AddOperation {
+                path,
+                value: value.clone(),
+            }),
+            Operation::Remove(_) => PatchOperation::Remove(RemoveOperation { path }),
+            Operation::Replace(_, value) => PatchOperation::Replace(ReplaceOperation {
+                path,
+                value: value.clone(),
+            }),
+            Operation::Test(_, value) => PatchOperation::Test(TestOperation {
+                path,
+                value: value.clone(),
+            }),
+        }
+    }
+}
+
+/// Applies a JSON Patch over the array of messages, returning the changes made.
+///
+/// Operations on whole messages become insertions, removals and replacements, and those within a
+/// message only convert that message to JSON and back, rather than the whole history. On failure,
+/// the messages are left as they were.
+pub(crate) fn apply_messages_patch<StateT: Clone>(
+    messages: &mut Vec<Message>,
+    patch: &Patch,
+) -> Result<Vec<AgentChange<StateT>>, String> {
+    // Each change applied, along with the change undoing it
+    let mut applied: Vec<(AgentChange<StateT>, AgentChange<StateT>)> = Vec::new();
+    let result = patch.iter().try_for_each(|operation| {
+        // Expanded one operation at a time, as a move reads the messages left by earlier ones
+        for operation in expand(messages, operation)? {
+            if let Some(change) = to_change(messages, &operation)? {
+                let undo = undo(messages, &change);
+                change.apply_to_messages(messages);
+                applied.push((change, undo));
+            }
+        }
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => Ok(applied.into_iter().map(|(change, _)| change).collect()),
+        Err(err) => {
+            for (_, undo) in applied.into_iter().rev() {
+                undo.apply_to_messages(messages);
+            }
+            Err(err)
+        }
+    }
+}
+
+fn expand(messages: &[Message], operation: &PatchOperation) -> Result<Vec<Operation>, String> {
+    Ok(match operation {
+        PatchOperation::Add(op) => vec![Operation::Add(op.path.clone(), op.value.clone())],
+        PatchOperation::Remove(op) => vec![Operation::Remove(op.path.clone())],
+        PatchOperation::Replace(op) => {
+            vec![Operation::Replace(op.path.clone(), op.value.clone())]
+        }
+        PatchOperation::Test(op) => vec![Operation::Test(op.path.clone(), op.value.clone())],
+        PatchOperation::Move(op) if op.from == op.path => vec![],
+        PatchOperation::Move(op) => vec![
+            Operation::Remove(op.from.clone()),
+            Operation::Add(op.path.clone(), value_at(messages, &op.from)?),
+        ],
+        PatchOperation::Copy(op) => vec![Operation::Add(
+            op.path.clone(),
+            value_at(messages, &op.from)?,
+        )],
+    })
+}
+
+fn value_at(messages: &[Message], pointer: &Pointer) -> Result<JsonValue, String> {
+    let Some((token, rest)) = pointer.split_front() else {
+        return to_json(messages);
+    };
+    let index = token
+        .to_index()
+        .map_err(|err| invalid_index(pointer, err))?
+        .for_len(messages.len())
+        .map_err(|err| invalid_index(pointer, err))?;
+    let json = to_json(&messages[index])?;
+    if rest.is_root() {
+        return Ok(json);
+    }
+    json.pointer(rest.as_str())
+        .cloned()
+        .ok_or_else(|| format!("No value at {pointer}"))
+}
+
+fn to_change<StateT>(
+    messages: &[Message],
+    operation: &Operation,
+) -> Result<Option<AgentChange<StateT>>, String> {
+    let path = operation.path();
+    let Some((token, rest)) = path.split_front() else {
+        // The whole array
+        let mut json = to_json(messages)?;
+        patch(&mut json, operation.at(PointerBuf::root()))?;
+        return Ok(match operation {
+            Operation::Test(..) => None,
+            _ => Some(AgentChange::SetMessages(from_json(json)?)),
+        });
+    };
+
+    let index = token.to_index().map_err(|err| invalid_index(path, err))?;
+    let bounded = |index: Result<usize, _>| index.map_err(|err| invalid_index(path, err));
+    if rest.is_root() {
+        return Ok(match operation {
+            Operation::Add(_, value) => Some(AgentChange::Insert {
+                index: bounded(index.for_len_incl(messages.len()))?,
+                message: from_json(value.clone())?,
+            }),
+            Operation::Remove(_) => Some(AgentChange::Remove {
+                index: bounded(index.for_len(messages.len()))?,
+            }),
+            Operation::Replace(_, value) => Some(AgentChange::Replace {
+                index: bounded(index.for_len(messages.len()))?,
+                message: from_json(value.clone())?,
+            }),
+            Operation::Test(_, value) => {
+                let index = bounded(index.for_len(messages.len()))?;
+                if to_json(&messages[index])? != *value {
+                    return Err(format!("Test failed at {path}"));
+                }
+                None
+            }
+        });
+    }
+
+    // Within a message, which alone is converted to JSON and back
+    let index = bounded(index.for_len(messages.len()))?;
+    let mut json = to_json(&messages[index])?;
+    patch(&mut json, operation.at(rest.to_buf()))?;
+    Ok(match operation {
+        Operation::Test(..) => None,
+        _ => Some(AgentChange::Replace {
+            index,
+            message: from_json(json)?,
+        }),
+    })
+}
+
+/// The change undoing `change`, which is about to be applied to `messages`
+fn undo<StateT>(messages: &[Message], change: &AgentChange<StateT>) -> AgentChange<StateT> {
+    match change {
+        AgentChange::Insert { index, .. } => AgentChange::Remove { index: *index },
+        AgentChange::Remove { index } => AgentChange::Insert {
+            index: *index,
+            message: messages[*index].clone(),
+        },
+        AgentChange::Replace { index, .. } => AgentChange::Replace {
+            index: *index,
+            message: messages[*index].clone(),
+        },
+        _ => AgentChange::SetMessages(messages.to_vec()),
+    }
+}
+
+fn invalid_index(path: &Pointer, err: impl std::fmt::Display) -> String {
+    format!("Invalid message index at {path}: {err}")
+}
+
+fn patch(json: &mut JsonValue, operation: PatchOperation) -> Result<(), String> {
+    json_patch::patch(json, &[operation]).map_err(|err| err.to_string())
+}
+
+fn to_json(value: &(impl serde::Serialize + ?Sized)) -> Result<JsonValue, String> {
+    serde_json::to_value(value).map_err(|err| err.to_string())
+}
+
+fn from_json<T: DeserializeOwned>(json: JsonValue) -> Result<T, String> {
+    serde_json::from_value(json).map_err(|err| format!("Invalid message: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch_from(json: JsonValue) -> Patch {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_operations_become_message_changes() {
+        let first = Message::new_user("one");
+        let second = Message::new_assistant("two");
+        let mut messages = vec![first.clone(), second.clone()];
+
+        let changes = apply_messages_patch::<JsonValue>(
+            &mut messages,
+            &patch_from(serde_json::json!([
+                {"op": "test", "path": "/1/content", "value": "two"},
+                {"op": "move", "from": "/1", "path": "/0"},
+                {"op": "copy", "from": "/1/content", "path": "/0/content"},
+            ])),
+        )
+        .unwrap();
+
+        assert_eq!(messages[0].id(), second.id());
+        assert_eq!(messages[0].content(), Some("one"));
+        assert_eq!(messages[1], first);
+        assert!(matches!(
+            changes.as_slice(),
+            [
+                AgentChange::Remove { index: 1 },
+                AgentChange::Insert { index: 0, .. },
+                AgentChange::Replace { index: 0, .. },
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_failing_patch_leaves_messages() {
+        let messages = vec![Message::new_user("one"), Message::new_assistant("two")];
+        let mut patched = messages.clone();
+
+        let err = apply_messages_patch::<JsonValue>(
+            &mut patched,
+            &patch_from(serde_json::json!([
+                {"op": "remove", "path": "/0"},
+                {"op": "replace", "path": "/0/content", "value": "three"},
+                {"op": "add", "path": "/-", "value": {"role": "user"}},
+            ])),
+        )
+        .unwrap_err();
+
+        assert!(err.starts_with("Invalid message"));
+        assert_eq!(patched, messages);
+    }
+}
@@ -0,0 +1,376 @@
+//! Retrying of event streams that fail with retryable errors.
+//!
+//! [`with_retry`] wraps a function building an event stream, and rebuilds the stream with
+//! exponential backoff whenever it fails with an error for which
+//! [`AgentError::is_retryable`] holds. It only depends on the stream of events, so it can wrap
+//! a client transport as well as a bridge to a flaky LLM provider.
+
+use crate::agent::AgentError;
+use crate::core::AgentState;
+use crate::core::canonical::canonical_hash;
+use crate::core::event::Event;
+use crate::core::extensions::{CustomEventExtension, Retry};
+use crate::stream::EventStream;
+use futures::StreamExt;
+use log::warn;
+use std::future::Future;
+use std::time::Duration;
+
+/// How often and how quickly a failed event stream is re-established.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of consecutive retries; the stream fails once they are exhausted.
+    /// Retries are counted again from the start, with the initial backoff, once a rebuilt
+    /// stream delivers a new event.
+    pub max_retries: u32,
+    /// The delay before the first retry
+    pub initial_backoff: Duration,
+    /// The upper bound of the delay between retries
+    pub max_backoff: Duration,
+    /// The factor by which the delay grows with every retry
+    pub multiplier: f64,
+}
+
+impl RetryPolicy {
+    /// The delay before the given retry, counting from 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.powi(attempt.saturating_sub(1) as i32);
+        self.initial_backoff.mul_f64(factor).min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+        }
+    }
+}
+
+struct RetryState<F, R, StateT: AgentState> {
+    make_stream: F,
+    /// Whether a rebuilt stream would resume after the last delivered event, rather than replay
+    resumes: R,
+    policy: RetryPolicy,
+    current: Option<EventStream<'static, StateT>>,
+    /// Retries made since the last new event was delivered
+    attempt: u32,
+    /// Delay to wait before building the next stream
+    delay: Option<Duration>,
+    /// Hashes of the events delivered so far, in order
+    delivered: Vec<Option<[u8; 32]>>,
+    /// Position of the current stream within the delivered events, while it replays them
+    replayed: Option<usize>,
+    finished: bool,
+}
+
+impl<F, R, StateT: AgentState> RetryState<F, R, StateT> {
+    /// Schedules a retry after `err`, returning the `RETRY` event to emit, or the error if it is
+    /// not retryable or the retries are exhausted.
+    fn retry(&mut self, err: AgentError) -> Result<Event<StateT>, AgentError> {
+        if !err.is_retryable() || self.attempt >= self.policy.max_retries {
+            return Err(err);
+        }
+        self.attempt += 1;
+        let delay = self.policy.backoff(self.attempt);
+        warn!(
+            "Retrying event stream (attempt {}) in {delay:?}: {err}",
+            self.attempt
+        );
+
+        self.current = None;
+        self.delay = Some(delay);
+        Ok(Retry {
+            attempt: self.attempt,
+            delay_ms: delay.as_millis() as u64,
+            error: err.to_string(),
+        }
+        .into_event()?)
+    }
+}
+
+/// Wraps a function building an event stream, rebuilding the stream according to `policy`
+/// whenever it fails with a retryable error.
+///
+/// Each retry is announced with a [`Retry`] custom event. A rebuilt stream may replay the events
+/// of the failed attempt from the start: events matching, by sequence position and
+/// [canonical hash](crate::core::canonical::canonical_hash), those already delivered are dropped,
+/// so each event is delivered once. Streams resuming where the previous attempt stopped, that is
+/// whose first event does not match the first delivered one, are passed through unchanged. A
+/// replay departing from the delivered events before catching up with them, as a
+/// non-deterministic run started over would, ends the stream with
+/// [`AgentError::ReplayDiverged`] rather than appending a second run to the first.
+///
+/// Telling replayed events apart costs a canonical hash of every event delivered, computed as
+/// it is delivered, and 32 bytes of memory per event for the lifetime of the stream, whether or
+/// not it ever fails. This is noticeable on long token-level streams; `HttpAgent` skips it once
+/// the server assigns event ids, as it then resumes with `Last-Event-ID` instead.
+///
+/// Errors that are not retryable, and the error ending the last allowed attempt, are yielded
+/// and end the stream.
+pub fn with_retry<StateT, F, Fut>(
+    make_stream: F,
+    policy: RetryPolicy,
+) -> EventStream<'static, StateT>
+where
+    StateT: AgentState,
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<EventStream<'static, StateT>, AgentError>> + Send,
+{
+    with_retry_resuming(make_stream, policy, || false)
+}
+
+/// Same as [`with_retry`], but without deduplicating replayed events while `resumes` holds, that
+/// is while a rebuilt stream would resume after the last event delivered.
+pub(crate) fn with_retry_resuming<StateT, F, Fut, R>(
+    make_stream: F,
+    policy: RetryPolicy,
+    resumes: R,
+) -> EventStream<'static, StateT>
+where
+    StateT: AgentState,
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<EventStream<'static, StateT>, AgentError>> + Send,
+    R: Fn() -> bool + Send + 'static,
+{
+    let state = RetryState {
+        make_stream,
+        resumes,
+        policy,
+        current: None,
+        attempt: 0,
+        delay: None,
+        delivered: Vec::new(),
+        replayed: None,
+        finished: false,
+    };
+
+    futures::stream::unfold(state, |mut state| async move {
+        if state.finished {
+            return None;
+        }
+        loop {
+            let Some(stream) = state.current.as_mut() else {
+                if let Some(delay) = state.delay.take() {
+                    tokio::time::sleep(delay).await;
+                }
+                match (state.make_stream)().await {
+                    Ok(stream) => {
+                        state.current = Some(stream);
+                        state.replayed = (!state.delivered.is_empty()).then_some(0);
+                        continue;
+                    }
+                    Err(err) => {
+                        let item = state.retry(err);
+                        state.finished = item.is_err();
+                        return Some((item, state));
+                    }
+                }
+            };
+
+            match stream.next().await {
+                Some(Ok(event)) => {
+                    if (state.resumes)() {
+                        // Nothing before this event will be replayed
+                        state.delivered.clear();
+                        state.replayed = None;
+                    } else {
+                        let hash = canonical_hash(&event).ok();
+                        if let Some(position) = state.replayed {
+                            // Events that cannot be hashed are taken to match at their position
+                            if state.delivered.get(position) == Some(&hash) {
+                                state.replayed = Some(position + 1);
+                                continue;
+                            }
+                            if position > 0 && position < state.delivered.len() {
+                                state.finished = true;
+                                return Some((Err(AgentError::ReplayDiverged { position }), state));
+                            }
+                            state.replayed = None;
+                        }
+                        state.delivered.push(hash);
+                    }
+                    // The stream made progress, so the next failure starts a new series of retries
+                    state.attempt = 0;
+                    return Some((Ok(event), state));
+                }
+                Some(Err(err)) => {
+                    let item = state.retry(err);
+                    state.finished = item.is_err();
+                    return Some((item, state));
+                }
+                None => return None,
+            }
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::JsonValue;
+    use crate::core::event::{BaseEvent, StepStartedEvent, TextMessageContentEvent};
+    use crate::core::types::MessageId;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            initial_backoff: Duration::ZERO,
+            ..RetryPolicy::default()
+        }
+    }
+
+    fn step(name: &str) -> Result<Event, AgentError> {
+        Ok(Event::StepStarted(StepStartedEvent {
+            base: BaseEvent {
+                timestamp: None,
+                raw_event: None,
+            },
+            step_name: name.to_string(),
+        }))
+    }
+
+    type NextStream = futures::future::Ready<Result<EventStream<'static, JsonValue>, AgentError>>;
+
+    /// Builds streams from the given attempts, one per call
+    fn attempts(
+        attempts: Vec<Vec<Result<Event, AgentError>>>,
+    ) -> (impl FnMut() -> NextStream, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let make_stream = move || {
+            let attempt = attempts[counter.fetch_add(1, Ordering::SeqCst)]
+                .iter()
+                .map(|item| match item {
+                    Ok(event) => Ok(event.clone()),
+                    Err(_) => stalled(),
+                })
+                .collect::<Vec<_>>();
+            futures::future::ready(Ok(futures::stream::iter(attempt).boxed()))
+        };
+        (make_stream, calls)
+    }
+
+    fn stalled() -> Result<Event, AgentError> {
+        Err(AgentError::Stalled {
+            max_gap: Duration::ZERO,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_retry_deduplicates_replayed_events() {
+        let (make_stream, calls) = attempts(vec![
+            vec![step("a"), step("b"), stalled()],
+            vec![step("a"), step("b"), step("c")],
+        ]);
+        let events: Vec<_> = with_retry(make_stream, policy()).collect().await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(events.len(), 4);
+        let retry = match &events[2] {
+            Ok(Event::Custom(e)) => Retry::from_custom_event(e).unwrap().unwrap(),
+            other => panic!("Expected retry event, got {other:?}"),
+        };
+        assert_eq!(retry.attempt, 1);
+        assert!(matches!(&events[3], Ok(Event::StepStarted(e)) if e.step_name == "c"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_fails_diverging_replays() {
+        let (make_stream, _) = attempts(vec![
+            vec![step("a"), step("b"), stalled()],
+            vec![step("a"), step("x"), step("c")],
+        ]);
+        let events: Vec<_> = with_retry(make_stream, policy()).collect().await;
+
+        assert_eq!(events.len(), 4);
+        assert!(matches!(
+            events[3],
+            Err(AgentError::ReplayDiverged { position: 1 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_retry_passes_resumed_streams_through() {
+        let message_id = MessageId::random();
+        let delta = |delta: &str| {
+            Ok(Event::TextMessageContent(
+                TextMessageContentEvent::new(message_id.clone(), delta.to_string()).unwrap(),
+            ))
+        };
+        let (make_stream, _) = attempts(vec![
+            vec![delta("Hel"), stalled()],
+            vec![delta("lo"), delta("!")],
+        ]);
+        let events: Vec<_> = with_retry(make_stream, policy()).collect().await;
+        assert_eq!(events.len(), 4);
+        assert!(matches!(&events[2], Ok(Event::TextMessageContent(e)) if e.delta == "lo"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_resuming_skips_deduplication() {
+        let (make_stream, _) = attempts(vec![vec![step("a"), stalled()], vec![step("a")]]);
+        let events: Vec<_> = with_retry_resuming(make_stream, policy(), || true)
+            .collect()
+            .await;
+        assert_eq!(events.len(), 3);
+        assert!(matches!(&events[2], Ok(Event::StepStarted(e)) if e.step_name == "a"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up() {
+        let (make_stream, calls) = attempts((0..3).map(|_| vec![stalled()]).collect());
+        let policy = RetryPolicy {
+            max_retries: 2,
+            ..policy()
+        };
+        let events: Vec<_> = with_retry(make_stream, policy.clone()).collect().await;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[2], Err(AgentError::Stalled { .. })));
+
+        // Errors that are not retryable end the stream immediately
+        let make_stream = || async { Err(AgentError::config("no url")) };
+        let events: Vec<Result<Event, _>> = with_retry(make_stream, policy).collect().await;
+        assert_eq!(events.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_count_resets_on_progress() {
+        let (make_stream, calls) = attempts(vec![
+            vec![step("a"), stalled()],
+            vec![step("a"), step("b"), stalled()],
+            vec![step("c"), stalled()],
+            vec![step("d")],
+        ]);
+        let policy = RetryPolicy {
+            max_retries: 1,
+            ..policy()
+        };
+        let events: Vec<_> = with_retry(make_stream, policy).collect().await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert_eq!(events.len(), 7);
+        assert!(events.iter().all(Result::is_ok));
+        for retry in [&events[1], &events[3], &events[5]] {
+            let retry = match retry {
+                Ok(Event::Custom(e)) => Retry::from_custom_event(e).unwrap().unwrap(),
+                other => panic!("Expected retry event, got {other:?}"),
+            };
+            assert_eq!(retry.attempt, 1);
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(3), Duration::from_secs(2));
+        assert_eq!(policy.backoff(10), Duration::from_secs(10));
+    }
+}
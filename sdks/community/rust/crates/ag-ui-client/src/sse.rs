@@ -6,7 +6,7 @@ use reqwest::Response;
 use std::pin::Pin;
 
 /// Represents a parsed Server-Sent Event
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SseEvent {
     /// The event type (from the "event:" field)
     pub event: Option<String>,
@@ -16,6 +16,19 @@ pub struct SseEvent {
 
     /// The event data (from the "data:" field)
     pub data: String,
+
+    /// Byte offset of the start of the frame within the response body
+    pub offset: usize,
+}
+
+/// A parsed Server-Sent Event together with the raw frame it was parsed from
+#[derive(Debug, Clone)]
+pub struct SseFrame {
+    /// The frame exactly as received on the wire, excluding the blank line terminating it
+    pub raw: String,
+
+    /// The parsed event
+    pub event: SseEvent,
 }
 
 /// Extension trait for processing Server-Sent Events (SSE) responses from reqwest::Response
@@ -48,6 +61,15 @@ pub trait SseResponseExt {
     async fn event_source(
         self,
     ) -> Pin<Box<dyn Stream<Item = Result<SseEvent, AgUiClientError>> + Send>>;
+
+    /// Converts a reqwest::Response into a Stream of SSE frames, retaining the raw text of
+    /// each frame next to the parsed event.
+    ///
+    /// Prefer [`SseResponseExt::event_source`] when the raw text is not needed, as it avoids
+    /// a copy per frame.
+    async fn frame_source(
+        self,
+    ) -> Pin<Box<dyn Stream<Item = Result<SseFrame, AgUiClientError>> + Send>>;
 }
 
 #[async_trait]
@@ -59,20 +81,34 @@ impl SseResponseExt for Response {
         let stream = self.bytes_stream();
 
         // Process the stream with type conversions
-        Box::pin(SseEventProcessor::new(stream))
+        Box::pin(SseEventProcessor::new(stream, process_raw_sse_events))
+    }
+
+    async fn frame_source(
+        self,
+    ) -> Pin<Box<dyn Stream<Item = Result<SseFrame, AgUiClientError>> + Send>> {
+        let stream = self.bytes_stream();
+        Box::pin(SseEventProcessor::new(stream, process_raw_sse_frames))
     }
 }
 
+/// Function splitting a buffer starting at the given byte offset into parsed items and the
+/// remaining (incomplete) buffer
+type BufferProcessor<T> = fn(&str, usize) -> (Vec<Result<T, AgUiClientError>>, String);
+
 /// A processor that converts a byte stream into an SSE event stream
 struct SseEventProcessor;
 
 impl SseEventProcessor {
     /// Creates a new SSE event processor
     #[allow(clippy::new_ret_no_self)]
-    fn new(
+    fn new<T>(
         stream: impl Stream<Item = Result<Bytes, reqwest::Error>> + 'static,
-    ) -> impl Stream<Item = Result<SseEvent, AgUiClientError>> {
+        process: BufferProcessor<T>,
+    ) -> impl Stream<Item = Result<T, AgUiClientError>> {
         let mut buffer = String::new();
+        // Number of bytes of the response consumed before the start of the buffer
+        let mut offset = 0;
 
         // Process the stream
         stream
@@ -89,7 +125,8 @@ impl SseEventProcessor {
                         buffer.push_str(&text);
 
                         // Process complete events from the buffer
-                        let (events, new_buffer) = process_raw_sse_events(&buffer);
+                        let (events, new_buffer) = process(&buffer, offset);
+                        offset += buffer.len() - new_buffer.len();
                         buffer = new_buffer;
 
                         events
@@ -108,8 +145,41 @@ impl SseEventProcessor {
 /// Returns a tuple of (events, new_buffer) where:
 /// - events: A vector of parsed events or errors
 /// - new_buffer: The remaining buffer that might contain incomplete events
-fn process_raw_sse_events(buffer: &str) -> (Vec<Result<SseEvent, AgUiClientError>>, String) {
-    let mut results = Vec::new();
+fn process_raw_sse_events(
+    buffer: &str,
+    offset: usize,
+) -> (Vec<Result<SseEvent, AgUiClientError>>, String) {
+    let (frames, new_buffer) = split_sse_frames(buffer);
+    let events = frames
+        .into_iter()
+        .map(|(start, raw)| parse_sse_event(raw).map(|event| event.at_offset(offset + start)))
+        .collect();
+    (events, new_buffer)
+}
+
+/// Process SSE data from a buffer string into SSE frames, keeping the raw text of each frame
+///
+/// Same as [`process_raw_sse_events`], but pairs each parsed event with its raw text.
+fn process_raw_sse_frames(
+    buffer: &str,
+    offset: usize,
+) -> (Vec<Result<SseFrame, AgUiClientError>>, String) {
+    let (frames, new_buffer) = split_sse_frames(buffer);
+    let frames = frames
+        .into_iter()
+        .map(|(start, raw)| {
+            parse_sse_event(raw).map(|event| SseFrame {
+                raw: raw.to_string(),
+                event: event.at_offset(offset + start),
+            })
+        })
+        .collect();
+    (frames, new_buffer)
+}
+
+/// Split a buffer into complete frames, paired with their byte offset within the buffer, and
+/// the remaining buffer
+fn split_sse_frames(buffer: &str) -> (Vec<(usize, &str)>, String) {
     let chunks: Vec<&str> = buffer.split("\n\n").collect();
 
     // If there's only one chunk and it doesn't end with a double newline,
@@ -126,12 +196,12 @@ fn process_raw_sse_events(buffer: &str) -> (Vec<Result<SseEvent, AgUiClientError
         &chunks[..chunks.len() - 1]
     };
 
-    // Process all complete events
-    for chunk in complete_chunks {
-        if !chunk.is_empty() {
-            results.push(parse_sse_event(chunk));
-        }
-    }
+    // Collect all complete, non-empty frames
+    let frames = complete_chunks
+        .iter()
+        .filter(|chunk| !chunk.is_empty())
+        .map(|chunk| (chunk.as_ptr() as usize - buffer.as_ptr() as usize, *chunk))
+        .collect();
 
     // If the buffer doesn't end with a double newline and we have chunks,
     // the last chunk is incomplete - keep it in the buffer
@@ -141,7 +211,7 @@ fn process_raw_sse_events(buffer: &str) -> (Vec<Result<SseEvent, AgUiClientError
         String::new()
     };
 
-    (results, new_buffer)
+    (frames, new_buffer)
 }
 
 /// Parse a single SSE event text into an SseEvent
@@ -170,7 +240,19 @@ fn parse_sse_event(event_text: &str) -> Result<SseEvent, AgUiClientError> {
     // Join all data lines with newlines
     let data = data_lines.join("\n");
 
-    Ok(SseEvent { event, id, data })
+    Ok(SseEvent {
+        event,
+        id,
+        data,
+        offset: 0,
+    })
+}
+
+impl SseEvent {
+    fn at_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -178,17 +260,11 @@ mod tests {
     use super::*;
     use serde::Deserialize;
 
-    #[derive(Deserialize, Debug, PartialEq)]
-    struct TestEvent {
-        event_type: String,
-        data: String,
-    }
-
     #[tokio::test]
     async fn test_process_raw_sse_events() {
         // Test with a single complete event
         let buffer = "data: {\"event_type\":\"test\",\"data\":\"hello\"}\n\n";
-        let (events, new_buffer) = process_raw_sse_events(buffer);
+        let (events, new_buffer) = process_raw_sse_events(buffer, 0);
         assert_eq!(events.len(), 1);
         assert_eq!(new_buffer, "");
         let event = events[0].as_ref().unwrap();
@@ -197,20 +273,20 @@ mod tests {
         // Test with multiple events
         let buffer = "data: {\"event_type\":\"test1\",\"data\":\"hello1\"}\n\n\
                       data: {\"event_type\":\"test2\",\"data\":\"hello2\"}\n\n";
-        let (events, new_buffer) = process_raw_sse_events(buffer);
+        let (events, new_buffer) = process_raw_sse_events(buffer, 0);
         assert_eq!(events.len(), 2);
         assert_eq!(new_buffer, "");
 
         // Test with incomplete event
         let buffer = "data: {\"event_type\":\"test\",\"data\":\"hello\"}";
-        let (events, new_buffer) = process_raw_sse_events(buffer);
+        let (events, new_buffer) = process_raw_sse_events(buffer, 0);
         assert_eq!(events.len(), 0);
         assert_eq!(new_buffer, buffer);
 
         // Test with complete and incomplete events
         let buffer = "data: {\"event_type\":\"test1\",\"data\":\"hello1\"}\n\n\
                       data: {\"event_type\":\"test2\",\"data\":\"hello2\"}";
-        let (events, new_buffer) = process_raw_sse_events(buffer);
+        let (events, new_buffer) = process_raw_sse_events(buffer, 0);
         assert_eq!(events.len(), 1);
         assert_eq!(
             new_buffer,
@@ -218,6 +294,27 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_process_raw_sse_frames() {
+        let buffer = "id: 1\nevent: ping\ndata: {\"a\":1}\n\n\
+                      data: incomplete";
+        let (frames, new_buffer) = process_raw_sse_frames(buffer, 0);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(new_buffer, "data: incomplete");
+        let frame = frames[0].as_ref().unwrap();
+        assert_eq!(frame.raw, "id: 1\nevent: ping\ndata: {\"a\":1}");
+        assert_eq!(frame.event.id, Some("1".to_string()));
+        assert_eq!(frame.event.data, "{\"a\":1}");
+    }
+
+    #[tokio::test]
+    async fn test_frame_offsets() {
+        let buffer = "data: first\n\ndata: second\n\n";
+        let (events, _) = process_raw_sse_events(buffer, 100);
+        assert_eq!(events[0].as_ref().unwrap().offset, 100);
+        assert_eq!(events[1].as_ref().unwrap().offset, 113);
+    }
+
     #[tokio::test]
     async fn test_parse_sse_event() {
         // Test with event and data
@@ -260,7 +357,7 @@ mod tests {
                       event: update\ndata: {\"id\":123,\"status\":\"ok\"}\n\n";
 
         // Process the raw events
-        let (raw_events, new_buffer) = process_raw_sse_events(buffer);
+        let (raw_events, new_buffer) = process_raw_sse_events(buffer, 0);
         assert_eq!(raw_events.len(), 2);
         assert_eq!(new_buffer, "");
 
@@ -314,7 +411,7 @@ mod tests {
                       event: message\ndata: {\"value\":\"message data\"}\n\n";
 
         // Process the raw events
-        let (raw_events, _) = process_raw_sse_events(buffer);
+        let (raw_events, _) = process_raw_sse_events(buffer, 0);
         assert_eq!(raw_events.len(), 3);
 
         // Parse event types as enum values
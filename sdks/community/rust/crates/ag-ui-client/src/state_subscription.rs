@@ -0,0 +1,249 @@
+//! Notifications for changes to parts of the state, rather than to the whole of it.
+//!
+//! [`AgentSubscriber::on_state_changed`] is called for every change of the state, and receives
+//! all of it. A [`StateSubscription`] instead watches a few JSON pointers into the state, and
+//! calls back with the operations of the `STATE_DELTA` events that touch them:
+//!
+//! ```no_run
+//! # use ag_ui_client::{Agent, HttpAgent, RunAgentParams};
+//! # use ag_ui_client::state_subscription::{StateChange, StateSubscription};
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let agent = HttpAgent::builder().with_url_str("http://127.0.0.1:3000/")?.build()?;
+//! let subscription = StateSubscription::new()
+//!     .watch("/user/name", |_path, change| println!("Name changed: {change:?}"))?
+//!     .watch("/items/-", |_path, _change| println!("An item changed"))?;
+//! agent.run_agent(&RunAgentParams::new(), (subscription,)).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use json_patch::jsonptr::PointerBuf;
+
+use crate::agent::{AgentError, AgentStateMutation};
+use crate::core::event::{StateDeltaEvent, StateSnapshotEvent};
+use crate::core::{AgentState, FwdProps, PatchOperation};
+use crate::subscriber::{AgentSubscriber, AgentSubscriberParams};
+
+/// A change to a watched part of the state.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StateChange<'a> {
+    /// The operations of a `STATE_DELTA` event touching the watched path, in order
+    Patched(Vec<&'a PatchOperation>),
+    /// The whole state was replaced by a `STATE_SNAPSHOT` event
+    Replaced,
+}
+
+/// Callback invoked with the watched path and its change.
+pub type StateCallback = Arc<dyn Fn(&str, &StateChange<'_>) + Send + Sync>;
+
+#[derive(Clone)]
+struct Watch {
+    path: PointerBuf,
+    callback: StateCallback,
+}
+
+/// A subscriber notifying callbacks of changes to the parts of the state they watch.
+///
+/// A path is touched by an operation on it, on one of its ancestors, such as the replacement of
+/// the object containing it, or on one of its descendants. The `-` token matches any token, so
+/// that `/items/-` watches every element of `items`. Elements of an array are identified by the
+/// index named in an operation, so an element shifted by the insertion or removal of an earlier
+/// element is not reported as changed, unless the array is watched as a whole.
+///
+/// Only the `STATE_SNAPSHOT` and `STATE_DELTA` events are watched, not changes made to the state
+/// by other subscribers.
+#[derive(Clone, Default)]
+pub struct StateSubscription {
+    watches: Vec<Watch>,
+}
+
+impl fmt::Debug for StateSubscription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.watches.iter().map(|watch| watch.path.as_str()))
+            .finish()
+    }
+}
+
+impl StateSubscription {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `callback` whenever a change touches `path`, a JSON pointer such as `/user/name`.
+    pub fn watch(
+        mut self,
+        path: &str,
+        callback: impl Fn(&str, &StateChange<'_>) + Send + Sync + 'static,
+    ) -> Result<Self, AgentError> {
+        let path = PointerBuf::parse(path)
+            .map_err(|err| AgentError::config(format!("Invalid JSON pointer {path:?}: {err}")))?;
+        self.watches.push(Watch {
+            path,
+            callback: Arc::new(callback),
+        });
+        Ok(self)
+    }
+
+    /// The paths watched, in the order they were registered
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.watches.iter().map(|watch| watch.path.as_str())
+    }
+
+    fn notify_delta(&self, delta: &[PatchOperation]) {
+        for watch in &self.watches {
+            let operations: Vec<&PatchOperation> = delta
+                .iter()
+                .filter(|operation| touches(operation, &watch.path))
+                .collect();
+            if !operations.is_empty() {
+                (watch.callback)(watch.path.as_str(), &StateChange::Patched(operations));
+            }
+        }
+    }
+
+    fn notify_snapshot(&self) {
+        for watch in &self.watches {
+            (watch.callback)(watch.path.as_str(), &StateChange::Replaced);
+        }
+    }
+}
+
+/// Whether an operation changes the value at `watched`
+fn touches(operation: &PatchOperation, watched: &PointerBuf) -> bool {
+    match operation {
+        PatchOperation::Add(op) => overlaps(&op.path, watched),
+        PatchOperation::Remove(op) => overlaps(&op.path, watched),
+        PatchOperation::Replace(op) => overlaps(&op.path, watched),
+        PatchOperation::Move(op) => overlaps(&op.from, watched) || overlaps(&op.path, watched),
+        PatchOperation::Copy(op) => overlaps(&op.path, watched),
+        PatchOperation::Test(_) => false,
+    }
+}
+
+/// Whether one of the paths is a prefix of the other
+fn overlaps(path: &PointerBuf, watched: &PointerBuf) -> bool {
+    path.tokens()
+        .zip(watched.tokens())
+        .all(|(token, pattern)| pattern.encoded() == "-" || token.encoded() == pattern.encoded())
+}
+
+#[async_trait::async_trait]
+impl<StateT, FwdPropsT> AgentSubscriber<StateT, FwdPropsT> for StateSubscription
+where
+    StateT: AgentState,
+    FwdPropsT: FwdProps,
+{
+    async fn on_state_snapshot_event(
+        &self,
+        _event: &StateSnapshotEvent<StateT>,
+        _params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<AgentStateMutation<StateT>, AgentError> {
+        self.notify_snapshot();
+        Ok(AgentStateMutation::default())
+    }
+
+    async fn on_state_delta_event(
+        &self,
+        event: &StateDeltaEvent,
+        _params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<AgentStateMutation<StateT>, AgentError> {
+        self.notify_delta(&event.delta);
+        Ok(AgentStateMutation::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::JsonValue;
+    use crate::core::event::{BaseEvent, Event};
+    use crate::event_handler::EventHandler;
+    use crate::event_handler::testing::{handle_events, input};
+    use crate::subscriber::Subscribers;
+    use std::sync::Mutex;
+
+    type Seen = Arc<Mutex<Vec<(String, Option<usize>)>>>;
+
+    #[tokio::test]
+    async fn test_state_subscription() {
+        let seen = Seen::default();
+        let record = |seen: &Seen| {
+            let seen = seen.clone();
+            move |path: &str, change: &StateChange<'_>| {
+                let operations = match change {
+                    StateChange::Patched(operations) => Some(operations.len()),
+                    StateChange::Replaced => None,
+                };
+                seen.lock().unwrap().push((path.to_string(), operations));
+            }
+        };
+        let subscription = StateSubscription::new()
+            .watch("/user/name", record(&seen))
+            .unwrap()
+            .watch("/items/-", record(&seen))
+            .unwrap();
+        assert!(
+            StateSubscription::new()
+                .watch("user", record(&seen))
+                .is_err()
+        );
+        assert_eq!(
+            subscription.paths().collect::<Vec<_>>(),
+            vec!["/user/name", "/items/-"]
+        );
+
+        let input = input();
+        let mut handler = EventHandler::new(
+            vec![],
+            serde_json::json!({"user": {"name": "Ada", "age": 36}, "items": []}),
+            &input,
+            Subscribers::from_subscriber(subscription),
+        );
+        let base = BaseEvent {
+            timestamp: None,
+            raw_event: None,
+        };
+        let delta = |patch: JsonValue| {
+            Event::StateDelta(StateDeltaEvent {
+                base: base.clone(),
+                delta: serde_json::from_value(patch).unwrap(),
+            })
+        };
+        let events = [
+            // Touches neither path
+            delta(serde_json::json!([
+                {"op": "replace", "path": "/user/age", "value": 37},
+                {"op": "test", "path": "/user/name", "value": "Ada"},
+            ])),
+            // Touches /items/- twice
+            delta(serde_json::json!([
+                {"op": "add", "path": "/items/-", "value": "a"},
+                {"op": "add", "path": "/items/0", "value": "b"},
+            ])),
+            // Replaces an ancestor of /user/name
+            delta(serde_json::json!([
+                {"op": "replace", "path": "/user", "value": {"name": "Grace"}},
+            ])),
+            Event::StateSnapshot(StateSnapshotEvent {
+                base: base.clone(),
+                snapshot: serde_json::json!({}),
+            }),
+        ];
+        handle_events(&mut handler, &events).await;
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                ("/items/-".to_string(), Some(2)),
+                ("/user/name".to_string(), Some(1)),
+                ("/user/name".to_string(), None),
+                ("/items/-".to_string(), None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,395 @@
+//! Persistence of conversations across runs and process restarts.
+//!
+//! A [`ConversationRecorder`] subscriber saves the messages and state of a thread to a
+//! [`ConversationStore`] after each successful run, and [`RunAgentParams::resume`] loads them
+//! back as the parameters of the next run:
+//!
+//! ```no_run
+//! # use ag_ui_client::{Agent, HttpAgent, RunAgentParams};
+//! # use ag_ui_client::core::types::ThreadId;
+//! # use ag_ui_client::store::{ConversationRecorder, FileConversationStore};
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let agent = HttpAgent::builder().with_url_str("http://127.0.0.1:3000/")?.build()?;
+//! # let thread_id = ThreadId::random();
+//! let store = FileConversationStore::new("conversations");
+//! let params: RunAgentParams = RunAgentParams::resume(&store, thread_id).await?;
+//! let params = params.user("And tomorrow?");
+//! agent.run_agent(&params, (ConversationRecorder::new(store),)).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Conversations can also be exported with [`ConversationExport`], as a JSON document that
+//! [`RunAgentParams::import`] turns back into run parameters, or as a Markdown transcript.
+
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::agent::{AgentError, AgentStateMutation, RunAgentParams};
+use crate::core::types::{Message, Role, RunId, ThreadId};
+use crate::core::{AgentState, FwdProps, JsonValue};
+use crate::subscriber::{AgentSubscriber, AgentSubscriberParams};
+
+/// The messages and state of a thread.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound(deserialize = ""))]
+pub struct Conversation<StateT: AgentState = JsonValue> {
+    pub messages: Vec<Message>,
+    pub state: StateT,
+}
+
+/// Storage of conversations by thread.
+#[async_trait::async_trait]
+pub trait ConversationStore<StateT: AgentState = JsonValue>: Send + Sync {
+    /// Loads the conversation of a thread, or `None` if nothing was saved for it.
+    async fn load(&self, thread_id: &ThreadId) -> Result<Option<Conversation<StateT>>, AgentError>;
+
+    /// Saves the conversation of a thread, replacing any previously saved one.
+    async fn save(
+        &self,
+        thread_id: &ThreadId,
+        conversation: &Conversation<StateT>,
+    ) -> Result<(), AgentError>;
+}
+
+/// A [`ConversationStore`] keeping each thread as a JSON file in a directory.
+#[derive(Debug, Clone)]
+pub struct FileConversationStore {
+    dir: PathBuf,
+}
+
+impl FileConversationStore {
+    /// Creates a store in the given directory, which is created on the first save.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, thread_id: &ThreadId) -> PathBuf {
+        self.dir.join(format!("{thread_id}.json"))
+    }
+}
+
+fn store_error(err: std::io::Error) -> AgentError {
+    AgentError::Store {
+        message: err.to_string(),
+    }
+}
+
+#[async_trait::async_trait]
+impl<StateT: AgentState> ConversationStore<StateT> for FileConversationStore {
+    async fn load(&self, thread_id: &ThreadId) -> Result<Option<Conversation<StateT>>, AgentError> {
+        match tokio::fs::read(self.path(thread_id)).await {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(store_error(err)),
+        }
+    }
+
+    async fn save(
+        &self,
+        thread_id: &ThreadId,
+        conversation: &Conversation<StateT>,
+    ) -> Result<(), AgentError> {
+        let bytes = serde_json::to_vec(conversation)?;
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(store_error)?;
+
+        // Write to a temporary file first, synced to disk before it replaces the previous one,
+        // so that a crash never leaves a truncated conversation
+        let path = self.path(thread_id);
+        let tmp_path = path.with_extension("json.tmp");
+        let mut file = tokio::fs::File::create(&tmp_path)
+            .await
+            .map_err(store_error)?;
+        file.write_all(&bytes).await.map_err(store_error)?;
+        file.sync_all().await.map_err(store_error)?;
+        drop(file);
+        tokio::fs::rename(&tmp_path, &path)
+            .await
+            .map_err(store_error)
+    }
+}
+
+/// Version of the JSON document written by [`ConversationExport::to_json`]
+pub const EXPORT_VERSION: u32 = 1;
+
+/// A conversation exported for download, or for continuing it elsewhere.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", bound(deserialize = ""))]
+pub struct ConversationExport<StateT: AgentState = JsonValue> {
+    /// The version of the document format, see [`EXPORT_VERSION`]
+    pub version: u32,
+    pub thread_id: ThreadId,
+    /// The last run of the conversation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<RunId>,
+    pub messages: Vec<Message>,
+    pub state: StateT,
+}
+
+impl<StateT: AgentState> ConversationExport<StateT> {
+    pub fn new(thread_id: ThreadId, conversation: Conversation<StateT>) -> Self {
+        Self {
+            version: EXPORT_VERSION,
+            thread_id,
+            run_id: None,
+            messages: conversation.messages,
+            state: conversation.state,
+        }
+    }
+
+    pub fn with_run_id(mut self, run_id: RunId) -> Self {
+        self.run_id = Some(run_id);
+        self
+    }
+
+    /// Serializes the conversation to a pretty-printed JSON document.
+    pub fn to_json(&self) -> Result<String, AgentError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parses a document written by [`ConversationExport::to_json`], rejecting documents of a
+    /// newer format version.
+    pub fn from_json(json: &str) -> Result<Self, AgentError> {
+        #[derive(Deserialize)]
+        struct Header {
+            version: u32,
+        }
+
+        let Header { version } = serde_json::from_str(json)?;
+        if version > EXPORT_VERSION {
+            return Err(AgentError::Store {
+                message: format!(
+                    "Unsupported conversation export version {version}, expected at most {EXPORT_VERSION}"
+                ),
+            });
+        }
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Renders the messages as a readable Markdown transcript, including tool calls and their
+    /// results.
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = format!("# Conversation `{}`\n", self.thread_id);
+        for message in &self.messages {
+            if let Message::Tool {
+                tool_call_id,
+                content,
+                error,
+                ..
+            } = message
+            {
+                markdown.push_str(&format!("\n## Tool result for `{tool_call_id}`\n\n"));
+                markdown.push_str(&fenced(content, ""));
+                if let Some(error) = error {
+                    markdown.push_str(&format!("\nError: {error}\n"));
+                }
+                continue;
+            }
+
+            markdown.push_str(&format!("\n## {}\n", role_title(&message.role())));
+            if let Some(content) = message.content().filter(|content| !content.is_empty()) {
+                markdown.push_str(&format!("\n{content}\n"));
+            }
+            for tool_call in message.tool_calls().unwrap_or_default() {
+                markdown.push_str(&format!(
+                    "\nTool call `{}` (`{}`):\n\n",
+                    tool_call.function.name, tool_call.id
+                ));
+                markdown.push_str(&fenced(&tool_call.function.arguments, "json"));
+            }
+        }
+        markdown
+    }
+
+    /// Turns the export into the parameters of a run continuing the conversation.
+    pub fn into_params<FwdPropsT: FwdProps>(self) -> RunAgentParams<StateT, FwdPropsT> {
+        let mut params = RunAgentParams::new_typed().with_thread_id(self.thread_id);
+        params.messages = self.messages;
+        params.state = self.state;
+        params
+    }
+}
+
+/// Capitalizes the wire name of a role, such as `"assistant"`, for use as a heading
+fn role_title(role: &Role) -> String {
+    let name = match serde_json::to_value(role) {
+        Ok(JsonValue::String(name)) => name,
+        _ => format!("{role:?}"),
+    };
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+/// Wraps text in a code block, with a fence longer than any run of backticks in the text
+fn fenced(text: &str, lang: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{fence}{lang}\n{text}\n{fence}\n")
+}
+
+/// Subscriber saving the conversation of the run's thread to a [`ConversationStore`] once the
+/// run has finished successfully.
+pub struct ConversationRecorder<S> {
+    store: S,
+}
+
+impl<S> ConversationRecorder<S> {
+    pub fn new(store: S) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait::async_trait]
+impl<StateT, FwdPropsT, S> AgentSubscriber<StateT, FwdPropsT> for ConversationRecorder<S>
+where
+    StateT: AgentState,
+    FwdPropsT: FwdProps,
+    S: ConversationStore<StateT>,
+{
+    async fn on_run_finalized(
+        &self,
+        params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<AgentStateMutation<StateT>, AgentError> {
+        let conversation = Conversation {
+            messages: params.messages.to_vec(),
+            state: params.state.clone(),
+        };
+        self.store
+            .save(&params.input.thread_id, &conversation)
+            .await?;
+        Ok(AgentStateMutation::default())
+    }
+}
+
+impl<StateT, FwdPropsT> RunAgentParams<StateT, FwdPropsT>
+where
+    StateT: AgentState,
+    FwdPropsT: FwdProps,
+{
+    /// Creates the parameters continuing the conversation of a thread saved in `store`, or
+    /// starting it if nothing was saved yet.
+    pub async fn resume<S>(store: &S, thread_id: ThreadId) -> Result<Self, AgentError>
+    where
+        S: ConversationStore<StateT> + ?Sized,
+    {
+        let conversation = store.load(&thread_id).await?;
+        let mut params = Self::new_typed().with_thread_id(thread_id);
+        if let Some(conversation) = conversation {
+            params.messages = conversation.messages;
+            params.state = conversation.state;
+        }
+        Ok(params)
+    }
+
+    /// Creates the parameters continuing a conversation exported with
+    /// [`ConversationExport::to_json`].
+    pub fn import(json: &str) -> Result<Self, AgentError> {
+        Ok(ConversationExport::from_json(json)?.into_params())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::MessageId;
+
+    #[tokio::test]
+    async fn test_file_store_resume() {
+        let dir = std::env::temp_dir().join(format!("ag-ui-store-{}", uuid::Uuid::new_v4()));
+        let store = FileConversationStore::new(&dir);
+        let thread_id = ThreadId::random();
+
+        let params: RunAgentParams = RunAgentParams::resume(&store, thread_id.clone())
+            .await
+            .unwrap();
+        assert_eq!(params.thread_id, Some(thread_id.clone()));
+        assert!(params.messages.is_empty());
+
+        let conversation = Conversation {
+            messages: vec![Message::new_user("Hello"), Message::new_assistant("Hi!")],
+            state: serde_json::json!({"count": 1}),
+        };
+        store.save(&thread_id, &conversation).await.unwrap();
+
+        let params: RunAgentParams = RunAgentParams::resume(&store, thread_id).await.unwrap();
+        assert_eq!(params.messages, conversation.messages);
+        assert_eq!(params.state, conversation.state);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_import() {
+        use crate::core::types::{FunctionCall, ToolCall, ToolCallId};
+
+        let tool_call_id = ToolCallId::from("call_1");
+        let conversation = Conversation {
+            messages: vec![
+                Message::new_user("Weather in Paris?"),
+                Message::Assistant {
+                    id: MessageId::random(),
+                    content: None,
+                    name: None,
+                    tool_calls: Some(vec![ToolCall::new(
+                        tool_call_id.clone(),
+                        FunctionCall {
+                            name: "get_weather".to_string(),
+                            arguments: r#"{"city":"Paris"}"#.to_string(),
+                        },
+                    )]),
+                    metadata: None,
+                },
+                Message::Tool {
+                    id: MessageId::random(),
+                    content: "``` 18°C".to_string(),
+                    tool_call_id,
+                    error: None,
+                    metadata: None,
+                },
+                Message::new_assistant("It is 18°C."),
+            ],
+            state: serde_json::json!({"city": "Paris"}),
+        };
+        let thread_id = ThreadId::random();
+        let export = ConversationExport::new(thread_id.clone(), conversation.clone())
+            .with_run_id(RunId::random());
+
+        let json = export.to_json().unwrap();
+        assert_eq!(ConversationExport::from_json(&json).unwrap(), export);
+        let params: RunAgentParams = RunAgentParams::import(&json).unwrap();
+        assert_eq!(params.thread_id, Some(thread_id.clone()));
+        assert_eq!(params.run_id, None);
+        assert_eq!(params.messages, conversation.messages);
+        assert_eq!(params.state, conversation.state);
+
+        let newer = json.replacen("\"version\": 1", "\"version\": 2", 1);
+        assert!(matches!(
+            ConversationExport::<JsonValue>::from_json(&newer),
+            Err(AgentError::Store { .. })
+        ));
+
+        assert_eq!(
+            export.to_markdown(),
+            format!(
+                "# Conversation `{thread_id}`\n\
+                 \n## User\n\nWeather in Paris?\n\
+                 \n## Assistant\n\nTool call `get_weather` (`call_1`):\n\n```json\n{{\"city\":\"Paris\"}}\n```\n\
+                 \n## Tool result for `call_1`\n\n````\n``` 18°C\n````\n\
+                 \n## Assistant\n\nIt is 18°C.\n"
+            )
+        );
+    }
+}
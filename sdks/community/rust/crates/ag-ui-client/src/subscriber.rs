@@ -4,15 +4,18 @@ use std::collections::HashMap;
 use std::slice::Iter;
 use std::sync::Arc;
 
-use crate::agent::{AgentError, AgentStateMutation};
+use crate::agent::{AgentChange, AgentError, AgentStateMutation};
 use crate::core::event::*;
+use crate::core::extensions::{Citation, MessageRevision, ToolResultDelta};
 use crate::core::types::{Message, RunAgentInput, ToolCall};
 use crate::core::{AgentState, FwdProps, JsonValue};
+use crate::tool_calls::ToolCallTracker;
 
 pub struct AgentSubscriberParams<'a, StateT: AgentState, FwdPropsT: FwdProps> {
     pub messages: &'a [Message],
     pub state: &'a StateT,
     pub input: &'a RunAgentInput<StateT, FwdPropsT>,
+    pub tool_calls: &'a ToolCallTracker,
 }
 
 /// Subscriber trait for hooking into Agent run lifecycle events.
@@ -198,6 +201,44 @@ where
         Ok(AgentStateMutation::default())
     }
 
+    /// Called for each `TOOL_RESULT_DELTA` custom event, with the output streamed so far for the
+    /// tool call. The event is also passed to [`AgentSubscriber::on_custom_event`].
+    async fn on_tool_result_delta(
+        &self,
+        delta: &ToolResultDelta,
+        _tool_result_buffer: &str,
+        params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<AgentStateMutation<StateT>, AgentError> {
+        Ok(AgentStateMutation::default())
+    }
+
+    /// Called for each `CITATION` custom event, with all citations received so far for the same
+    /// message. A citation may refer to content that has not been streamed yet;
+    /// [`Citation::cited_text`] resolves it against the current message content. Revisions of the
+    /// message shift the citations after the revised text and drop those citing it.
+    /// The event is also passed to [`AgentSubscriber::on_custom_event`].
+    async fn on_citation(
+        &self,
+        citation: &Citation,
+        _message_citations: &[Citation],
+        params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<AgentStateMutation<StateT>, AgentError> {
+        Ok(AgentStateMutation::default())
+    }
+
+    /// Called for each `MESSAGE_REVISION` custom event, once the revision has been applied to the
+    /// message, with the content it replaced. The revision is also reported to
+    /// [`AgentSubscriber::on_changes`] as an [`AgentChange::ReviseContent`], and the event passed
+    /// to [`AgentSubscriber::on_custom_event`].
+    async fn on_message_revision(
+        &self,
+        revision: &MessageRevision,
+        _previous_content: &str,
+        params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<AgentStateMutation<StateT>, AgentError> {
+        Ok(AgentStateMutation::default())
+    }
+
     async fn on_text_message_chunk_event(
         &self,
         event: &TextMessageChunkEvent,
@@ -255,6 +296,19 @@ where
     }
 
     // State changes
+    /// Called with the incremental changes made while handling an event, before
+    /// [`AgentSubscriber::on_messages_changed`] and [`AgentSubscriber::on_state_changed`].
+    /// Replacements made through the snapshot form of [`AgentStateMutation`] are included as
+    /// [`AgentChange::SetMessages`] and [`AgentChange::SetState`], and state deltas as
+    /// [`AgentChange::PatchState`].
+    async fn on_changes(
+        &self,
+        changes: &[AgentChange<StateT>],
+        params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
+    ) -> Result<(), AgentError> {
+        Ok(())
+    }
+
     async fn on_messages_changed(
         &self,
         params: AgentSubscriberParams<'async_trait, StateT, FwdPropsT>,
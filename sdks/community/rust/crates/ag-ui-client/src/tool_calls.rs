@@ -0,0 +1,239 @@
+//! Tracking of which tool calls are still waiting for a result.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use futures::FutureExt;
+use futures::channel::oneshot;
+
+use crate::core::types::{Message, MessageId, ToolCallId};
+
+/// The lifecycle of a tool call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolCallStatus {
+    /// The arguments are still being streamed
+    Streaming,
+    /// The arguments are complete, and the result has not been received yet
+    Pending,
+    /// The result has been received
+    Resolved { content: String },
+}
+
+/// A tool call seen by a [`ToolCallTracker`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackedToolCall {
+    pub id: ToolCallId,
+    pub name: String,
+    pub parent_message_id: Option<MessageId>,
+    pub arguments: String,
+    pub status: ToolCallStatus,
+}
+
+impl TrackedToolCall {
+    /// Whether the tool call has not been resolved yet
+    pub fn is_pending(&self) -> bool {
+        !matches!(self.status, ToolCallStatus::Resolved { .. })
+    }
+}
+
+#[derive(Debug, Default)]
+struct TrackerInner {
+    /// Tool calls in the order they were started
+    calls: Vec<TrackedToolCall>,
+    waiters: HashMap<ToolCallId, Vec<oneshot::Sender<String>>>,
+}
+
+impl TrackerInner {
+    fn get_mut(&mut self, id: &ToolCallId) -> Option<&mut TrackedToolCall> {
+        self.calls.iter_mut().rfind(|call| call.id == *id)
+    }
+}
+
+/// A read-only projection of the tool calls of a conversation and whether they have been
+/// resolved, kept up to date from the `TOOL_CALL_*` events of a run.
+///
+/// The tracker is a cheap handle to shared state: clones observe the same tool calls.
+#[derive(Debug, Clone, Default)]
+pub struct ToolCallTracker {
+    inner: Arc<Mutex<TrackerInner>>,
+}
+
+impl ToolCallTracker {
+    /// Creates a tracker knowing about the tool calls in a message history. Tool calls without a
+    /// tool message carrying their result are pending.
+    pub fn from_messages(messages: &[Message]) -> Self {
+        let mut inner = TrackerInner::default();
+        for message in messages {
+            if let Some(tool_calls) = message.tool_calls() {
+                inner
+                    .calls
+                    .extend(tool_calls.iter().map(|tool_call| TrackedToolCall {
+                        id: tool_call.id.clone(),
+                        name: tool_call.function.name.clone(),
+                        parent_message_id: Some(message.id().clone()),
+                        arguments: tool_call.function.arguments.clone(),
+                        status: ToolCallStatus::Pending,
+                    }));
+            }
+            if let Message::Tool {
+                tool_call_id,
+                content,
+                ..
+            } = message
+                && let Some(call) = inner.get_mut(tool_call_id)
+            {
+                call.status = ToolCallStatus::Resolved {
+                    content: content.clone(),
+                };
+            }
+        }
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// The tool calls that have not been resolved yet, in the order they were started
+    pub fn pending(&self) -> Vec<TrackedToolCall> {
+        self.filter(|call| call.is_pending())
+    }
+
+    /// The tool calls that have been resolved, in the order they were started
+    pub fn resolved(&self) -> Vec<TrackedToolCall> {
+        self.filter(|call| !call.is_pending())
+    }
+
+    pub fn get(&self, id: &ToolCallId) -> Option<TrackedToolCall> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.get_mut(id).cloned()
+    }
+
+    /// Returns a future resolving with the result content of a tool call once it is received.
+    ///
+    /// The future resolves immediately if the tool call has already been resolved, and with
+    /// `None` if every handle to the tracker is dropped before the result arrives.
+    pub fn completion(&self, id: &ToolCallId) -> impl Future<Output = Option<String>> + use<> {
+        let mut inner = self.inner.lock().unwrap();
+        let (sender, receiver) = oneshot::channel();
+        match inner.get_mut(id).map(|call| &call.status) {
+            Some(ToolCallStatus::Resolved { content }) => {
+                let _ = sender.send(content.clone());
+            }
+            _ => inner.waiters.entry(id.clone()).or_default().push(sender),
+        }
+        receiver.map(Result::ok)
+    }
+
+    fn filter(&self, predicate: impl Fn(&TrackedToolCall) -> bool) -> Vec<TrackedToolCall> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .calls
+            .iter()
+            .filter(|call| predicate(call))
+            .cloned()
+            .collect()
+    }
+
+    pub(crate) fn start(&self, id: &ToolCallId, name: &str, parent_message_id: Option<&MessageId>) {
+        self.inner.lock().unwrap().calls.push(TrackedToolCall {
+            id: id.clone(),
+            name: name.to_string(),
+            parent_message_id: parent_message_id.cloned(),
+            arguments: String::new(),
+            status: ToolCallStatus::Streaming,
+        });
+    }
+
+    pub(crate) fn append_args(&self, id: &ToolCallId, delta: &str) {
+        if let Some(call) = self.inner.lock().unwrap().get_mut(id) {
+            call.arguments.push_str(delta);
+        }
+    }
+
+    pub(crate) fn end(&self, id: &ToolCallId) {
+        if let Some(call) = self.inner.lock().unwrap().get_mut(id)
+            && call.status == ToolCallStatus::Streaming
+        {
+            call.status = ToolCallStatus::Pending;
+        }
+    }
+
+    pub(crate) fn resolve(&self, id: &ToolCallId, content: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(call) = inner.get_mut(id) {
+            call.status = ToolCallStatus::Resolved {
+                content: content.to_string(),
+            };
+        }
+        for waiter in inner.waiters.remove(id).unwrap_or_default() {
+            let _ = waiter.send(content.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::{FunctionCall, ToolCall};
+
+    #[tokio::test]
+    async fn test_tracker_lifecycle() {
+        let resolved_id = ToolCallId::random();
+        let history_id = ToolCallId::random();
+        let assistant = Message::Assistant {
+            id: MessageId::random(),
+            content: None,
+            name: None,
+            tool_calls: Some(vec![
+                ToolCall::new(
+                    resolved_id.clone(),
+                    FunctionCall {
+                        name: "search".to_string(),
+                        arguments: "{}".to_string(),
+                    },
+                ),
+                ToolCall::new(
+                    history_id.clone(),
+                    FunctionCall {
+                        name: "confirm".to_string(),
+                        arguments: "{}".to_string(),
+                    },
+                ),
+            ]),
+            metadata: None,
+        };
+        let result = Message::Tool {
+            id: MessageId::random(),
+            content: "found".to_string(),
+            tool_call_id: resolved_id.clone(),
+            error: None,
+            metadata: None,
+        };
+        let tracker = ToolCallTracker::from_messages(&[assistant, result]);
+        assert_eq!(tracker.resolved().len(), 1);
+        assert_eq!(tracker.pending()[0].id, history_id);
+
+        let streamed_id = ToolCallId::random();
+        tracker.start(&streamed_id, "weather", None);
+        tracker.append_args(&streamed_id, r#"{"city":"#);
+        tracker.append_args(&streamed_id, r#""Paris"}"#);
+        assert_eq!(
+            tracker.get(&streamed_id).unwrap().status,
+            ToolCallStatus::Streaming
+        );
+        tracker.end(&streamed_id);
+        assert_eq!(tracker.pending().len(), 2);
+
+        let completion = tracker.completion(&streamed_id);
+        tracker.resolve(&streamed_id, "sunny");
+        assert_eq!(completion.await, Some("sunny".to_string()));
+        assert_eq!(
+            tracker.get(&streamed_id).unwrap().arguments,
+            r#"{"city":"Paris"}"#
+        );
+        assert_eq!(
+            tracker.completion(&resolved_id).await,
+            Some("found".to_string())
+        );
+    }
+}
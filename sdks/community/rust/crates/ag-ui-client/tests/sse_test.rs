@@ -1,3 +1,4 @@
+use ag_ui_client::core::event::Event;
 use ag_ui_client::sse::SseResponseExt;
 use futures::StreamExt;
 use reqwest::Client;
@@ -113,3 +114,33 @@ async fn test_sse_with_json_data() {
         }
     }
 }
+
+/// The golden SSE encoding of the events of `ag-ui-core`, see its `tests/fixtures`
+const GOLDEN_SSE: &str = include_str!("../../ag-ui-core/tests/fixtures/events.sse");
+const GOLDEN_JSONL: &str = include_str!("../../ag-ui-core/tests/fixtures/events.jsonl");
+
+#[tokio::test]
+async fn test_golden_sse_is_decoded() {
+    // Sent in small chunks, so that frames are split across them
+    let chars: Vec<char> = GOLDEN_SSE.chars().collect();
+    let chunks: Vec<Result<String, std::io::Error>> = chars
+        .chunks(7)
+        .map(|chunk| Ok(chunk.iter().collect()))
+        .collect();
+    let body = reqwest::Body::wrap_stream(futures::stream::iter(chunks));
+    let response = reqwest::Response::from(http::Response::new(body));
+
+    let frames: Vec<_> = response
+        .event_source()
+        .await
+        .map(|frame| frame.unwrap())
+        .collect()
+        .await;
+    let expected: Vec<&str> = GOLDEN_JSONL.lines().collect();
+    assert_eq!(frames.len(), expected.len());
+    for (frame, json) in frames.iter().zip(expected) {
+        assert_eq!(frame.data, json);
+        let decoded: Event = serde_json::from_str(&frame.data).unwrap();
+        assert_eq!(decoded, serde_json::from_str::<Event>(json).unwrap());
+    }
+}
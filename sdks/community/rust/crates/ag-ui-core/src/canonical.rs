@@ -0,0 +1,121 @@
+//! Canonical JSON serialization and hashing.
+//!
+//! Two values that serialize to the same JSON modulo key order, number representation and the
+//! excluded fields produce the same canonical form, and thus the same hash. This makes the hash
+//! suitable for caching, idempotency keys and state verification across processes.
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+
+/// Fields excluded by [`canonical_hash`]: envelope fields of events and run inputs that differ
+/// between otherwise identical runs.
+pub const DEFAULT_EXCLUDED_FIELDS: &[&str] = &["timestamp", "runId"];
+
+/// Returns the canonical JSON serialization of a value.
+///
+/// Object keys are sorted, and numbers with an integral value are written as integers, so that
+/// `1.0` and `1` are equal. Fields listed in `excluded` are removed: a name such as `timestamp`
+/// only matches a field of the top-level object, like the envelope fields of an event, and a
+/// JSON pointer such as `/state/updatedAt` matches the field at that path. Fields of the same
+/// name in nested, user-owned values such as the state are kept.
+pub fn canonical_json<T: Serialize + ?Sized>(
+    value: &T,
+    excluded: &[&str],
+) -> Result<String, serde_json::Error> {
+    let value = serde_json::to_value(value)?;
+    let mut out = String::new();
+    write_canonical(&value, excluded, &mut String::new(), &mut out)?;
+    Ok(out)
+}
+
+/// Returns the SHA-256 hash of the canonical JSON serialization of a value, excluding
+/// [`DEFAULT_EXCLUDED_FIELDS`].
+pub fn canonical_hash<T: Serialize + ?Sized>(value: &T) -> Result<[u8; 32], serde_json::Error> {
+    canonical_hash_excluding(value, DEFAULT_EXCLUDED_FIELDS)
+}
+
+/// Same as [`canonical_hash`], with an explicit list of excluded fields.
+pub fn canonical_hash_excluding<T: Serialize + ?Sized>(
+    value: &T,
+    excluded: &[&str],
+) -> Result<[u8; 32], serde_json::Error> {
+    let json = canonical_json(value, excluded)?;
+    Ok(Sha256::digest(json.as_bytes()).into())
+}
+
+/// Whether the field `key` of the object at `path`, a JSON pointer, is excluded
+fn is_excluded(excluded: &[&str], path: &str, key: &str) -> bool {
+    excluded.iter().any(|field| {
+        if field.starts_with('/') {
+            field
+                .strip_prefix(path)
+                .and_then(|rest| rest.strip_prefix('/'))
+                .is_some_and(|token| token == escape(key))
+        } else {
+            path.is_empty() && *field == key
+        }
+    })
+}
+
+/// Escapes a key for use as a JSON pointer token
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn write_canonical(
+    value: &JsonValue,
+    excluded: &[&str],
+    path: &mut String,
+    out: &mut String,
+) -> Result<(), serde_json::Error> {
+    match value {
+        JsonValue::Object(map) => {
+            let mut entries: Vec<_> = map
+                .iter()
+                .filter(|(key, _)| !is_excluded(excluded, path, key))
+                .collect();
+            entries.sort_by_key(|(key, _)| *key);
+
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::to_string(key)?);
+                out.push(':');
+                let len = path.len();
+                path.push('/');
+                path.push_str(&escape(key));
+                write_canonical(value, excluded, path, out)?;
+                path.truncate(len);
+            }
+            out.push('}');
+        }
+        JsonValue::Array(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                let len = path.len();
+                path.push('/');
+                path.push_str(&i.to_string());
+                write_canonical(value, excluded, path, out)?;
+                path.truncate(len);
+            }
+            out.push(']');
+        }
+        JsonValue::Number(number) => match number.as_f64() {
+            // Integral floats are written as integers, within the range where f64 is exact
+            Some(float)
+                if number.is_f64() && float.fract() == 0.0 && float.abs() < 2f64.powi(53) =>
+            {
+                out.push_str(&(float as i64).to_string())
+            }
+            _ => out.push_str(&number.to_string()),
+        },
+        other => out.push_str(&serde_json::to_string(other)?),
+    }
+    Ok(())
+}
@@ -1,13 +1,32 @@
-use crate::JsonValue;
 use crate::state::AgentState;
 use crate::types::{Message, Role};
 use crate::types::{MessageId, RunId, ThreadId, ToolCallId};
-use serde::{Deserialize, Serialize};
+use crate::{JsonValue, Patch};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Defines [`EventType`] with the given event types of the protocol, along with
+/// [`EventType::ALL`] listing them.
+macro_rules! event_types {
+    ($($(#[doc = $doc:literal])* $variant:ident,)*) => {
+        /// Event types for AG-UI protocol
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+        #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+        #[non_exhaustive]
+        pub enum EventType {
+            $($(#[doc = $doc])* $variant,)*
+            /// Event of a type not recognised by this version of the crate
+            Unknown,
+        }
 
-/// Event types for AG-UI protocol
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum EventType {
+        impl EventType {
+            /// The event types of the protocol, that is every event type but
+            /// [`EventType::Unknown`]
+            pub const ALL: &'static [EventType] = &[$(EventType::$variant,)*];
+        }
+    };
+}
+
+event_types! {
     /// Event indicating the start of a text message
     TextMessageStart,
     /// Event containing a piece of text message content
@@ -240,7 +259,7 @@ pub struct StateSnapshotEvent<StateT: AgentState = JsonValue> {
 pub struct StateDeltaEvent {
     #[serde(flatten)]
     pub base: BaseEvent,
-    pub delta: Vec<JsonValue>,
+    pub delta: Patch,
 }
 
 /// Event containing a snapshot of the messages.
@@ -337,8 +356,10 @@ pub struct StepFinishedEvent {
 #[serde(
     tag = "type",
     rename_all = "SCREAMING_SNAKE_CASE",
-    bound(deserialize = "")
+    bound(deserialize = ""),
+    remote = "Self"
 )]
+#[non_exhaustive]
 pub enum Event<StateT: AgentState = JsonValue> {
     /// Signals the start of a text message from an agent.
     /// Contains the message ID and role information.
@@ -433,6 +454,57 @@ pub enum Event<StateT: AgentState = JsonValue> {
     /// Signals the completion of a step within an agent run.
     /// Contains the name of the completed step.
     StepFinished(StepFinishedEvent),
+
+    /// An event whose type is not recognised by this version of the crate.
+    /// Only produced by [`Event::from_value_compat`], and serialized back as its original payload.
+    #[serde(skip)]
+    Unknown {
+        /// The `type` of the event as received
+        type_name: String,
+        /// The event as received, including its `type` field
+        payload: JsonValue,
+    },
+}
+
+impl<StateT: AgentState> Serialize for Event<StateT> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Event::Unknown { payload, .. } => payload.serialize(serializer),
+            _ => Self::serialize(self, serializer),
+        }
+    }
+}
+
+impl<'de, StateT: AgentState> Deserialize<'de> for Event<StateT> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::deserialize(deserializer)
+    }
+}
+
+impl<StateT: AgentState> Event<StateT> {
+    /// Deserializes an event in compatibility mode.
+    ///
+    /// Events with a `type` this version of the crate does not know about are returned as
+    /// [`Event::Unknown`] instead of failing, so that clients keep working when new event types
+    /// are added to the protocol. Events of a known type are deserialized as usual.
+    pub fn from_value_compat(value: JsonValue) -> Result<Self, serde_json::Error> {
+        let type_name = match value.get("type") {
+            Some(JsonValue::String(type_name)) => type_name.clone(),
+            _ => return serde_json::from_value(value),
+        };
+        match serde_json::from_value::<EventType>(JsonValue::String(type_name.clone())) {
+            Ok(event_type) if event_type != EventType::Unknown => serde_json::from_value(value),
+            _ => Ok(Event::Unknown {
+                type_name,
+                payload: value,
+            }),
+        }
+    }
+
+    /// Returns true if this event's type is not recognised by this version of the crate.
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, Event::Unknown { .. })
+    }
 }
 
 impl Event {
@@ -463,6 +535,7 @@ impl Event {
             Event::RunError(_) => EventType::RunError,
             Event::StepStarted(_) => EventType::StepStarted,
             Event::StepFinished(_) => EventType::StepFinished,
+            Event::Unknown { .. } => EventType::Unknown,
         }
     }
 
@@ -493,6 +566,7 @@ impl Event {
             Event::RunError(e) => e.base.timestamp,
             Event::StepStarted(e) => e.base.timestamp,
             Event::StepFinished(e) => e.base.timestamp,
+            Event::Unknown { payload, .. } => payload.get("timestamp").and_then(JsonValue::as_f64),
         }
     }
 }
@@ -563,3 +637,85 @@ impl TextMessageContentEvent {
         self
     }
 }
+
+impl CustomEvent {
+    pub fn new(name: impl Into<String>, value: JsonValue) -> Self {
+        Self {
+            base: BaseEvent {
+                timestamp: None,
+                raw_event: None,
+            },
+            name: name.into(),
+            value,
+        }
+    }
+
+    pub fn with_timestamp(mut self, timestamp: f64) -> Self {
+        self.base.timestamp = Some(timestamp);
+        self
+    }
+}
+
+impl ToolCallStartEvent {
+    pub fn new(tool_call_id: impl Into<ToolCallId>, tool_call_name: impl Into<String>) -> Self {
+        Self {
+            base: BaseEvent {
+                timestamp: None,
+                raw_event: None,
+            },
+            tool_call_id: tool_call_id.into(),
+            tool_call_name: tool_call_name.into(),
+            parent_message_id: None,
+        }
+    }
+
+    pub fn with_parent_message_id(mut self, parent_message_id: impl Into<MessageId>) -> Self {
+        self.parent_message_id = Some(parent_message_id.into());
+        self
+    }
+}
+
+impl ToolCallArgsEvent {
+    pub fn new(tool_call_id: impl Into<ToolCallId>, delta: impl Into<String>) -> Self {
+        Self {
+            base: BaseEvent {
+                timestamp: None,
+                raw_event: None,
+            },
+            tool_call_id: tool_call_id.into(),
+            delta: delta.into(),
+        }
+    }
+}
+
+impl ToolCallEndEvent {
+    pub fn new(tool_call_id: impl Into<ToolCallId>) -> Self {
+        Self {
+            base: BaseEvent {
+                timestamp: None,
+                raw_event: None,
+            },
+            tool_call_id: tool_call_id.into(),
+        }
+    }
+}
+
+impl RunFinishedEvent {
+    pub fn new(thread_id: impl Into<ThreadId>, run_id: impl Into<RunId>) -> Self {
+        Self {
+            base: BaseEvent {
+                timestamp: None,
+                raw_event: None,
+            },
+            thread_id: thread_id.into(),
+            run_id: run_id.into(),
+            result: None,
+        }
+    }
+
+    /// Sets the result of the run, serialized from a typed value.
+    pub fn with_result<T: Serialize>(mut self, result: &T) -> Result<Self, serde_json::Error> {
+        self.result = Some(serde_json::to_value(result)?);
+        Ok(self)
+    }
+}
@@ -0,0 +1,78 @@
+use crate::types::MessageId;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+
+/// Start of a stream of audio for a message, such as synthesized speech of its content.
+///
+/// Sent as an `AUDIO_START` custom event, followed by any number of [`AudioChunk`]s and an
+/// [`AudioEnd`] with the same message ID.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioStart {
+    #[serde(rename = "messageId")]
+    pub message_id: MessageId,
+    /// The audio encoding, e.g. `"pcm16"`, `"opus"` or `"mp3"`
+    pub codec: String,
+    #[serde(rename = "sampleRate")]
+    pub sample_rate: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<u16>,
+}
+
+impl AudioStart {
+    pub fn new(
+        message_id: impl Into<MessageId>,
+        codec: impl Into<String>,
+        sample_rate: u32,
+    ) -> Self {
+        Self {
+            message_id: message_id.into(),
+            codec: codec.into(),
+            sample_rate,
+            channels: None,
+        }
+    }
+
+    pub fn with_channels(mut self, channels: u16) -> Self {
+        self.channels = Some(channels);
+        self
+    }
+}
+
+/// A chunk of encoded audio, sent as an `AUDIO_CHUNK` custom event.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioChunk {
+    #[serde(rename = "messageId")]
+    pub message_id: MessageId,
+    /// The audio bytes, base64 encoded
+    pub data: String,
+}
+
+impl AudioChunk {
+    pub fn new(message_id: impl Into<MessageId>, data: impl AsRef<[u8]>) -> Self {
+        Self {
+            message_id: message_id.into(),
+            data: BASE64.encode(data),
+        }
+    }
+
+    /// Decodes the audio bytes of this chunk.
+    pub fn decode(&self) -> Result<Vec<u8>, base64::DecodeError> {
+        BASE64.decode(&self.data)
+    }
+}
+
+/// End of a stream of audio, sent as an `AUDIO_END` custom event.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioEnd {
+    #[serde(rename = "messageId")]
+    pub message_id: MessageId,
+}
+
+impl AudioEnd {
+    pub fn new(message_id: impl Into<MessageId>) -> Self {
+        Self {
+            message_id: message_id.into(),
+        }
+    }
+}
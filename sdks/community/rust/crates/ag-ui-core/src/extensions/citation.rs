@@ -0,0 +1,47 @@
+use crate::types::MessageId;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::ops::Range;
+
+/// A source cited by a range of the content of a message.
+///
+/// Sent as a `CITATION` custom event. The range is given in characters of the message content
+/// and may extend past the content streamed so far, in which case it is resolved once later
+/// deltas reach it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Citation {
+    #[serde(rename = "messageId")]
+    pub message_id: MessageId,
+    pub start: usize,
+    pub end: usize,
+    /// The cited source, such as a URL with a title or a document reference
+    pub source: JsonValue,
+}
+
+impl Citation {
+    pub fn new(message_id: impl Into<MessageId>, range: Range<usize>, source: JsonValue) -> Self {
+        Self {
+            message_id: message_id.into(),
+            start: range.start,
+            end: range.end,
+            source,
+        }
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// Returns the cited part of the message content, or `None` if the content does not reach
+    /// the end of the range yet.
+    pub fn cited_text<'a>(&self, content: &'a str) -> Option<&'a str> {
+        let byte_offset = |chars: usize| {
+            content
+                .char_indices()
+                .map(|(offset, _)| offset)
+                .chain(std::iter::once(content.len()))
+                .nth(chars)
+        };
+        content.get(byte_offset(self.start)?..byte_offset(self.end)?)
+    }
+}
@@ -0,0 +1,101 @@
+use crate::types::MessageId;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// A revision of the content of a message that has already been streamed, such as an agent
+/// correcting its own output. Sent as a `MESSAGE_REVISION` custom event.
+///
+/// The revision either replaces the whole content, or applies a list of [`TextEdit`]s to it when
+/// the change is small compared to the message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageRevision {
+    #[serde(rename = "messageId")]
+    pub message_id: MessageId,
+    /// The revised content, replacing the current content of the message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Edits applied in order to the current content, if `content` is absent
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edits: Vec<TextEdit>,
+}
+
+/// Replacement of a range of the content of a message, given in characters like the range of a
+/// [`Citation`](crate::extensions::Citation).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, text: impl Into<String>) -> Self {
+        Self {
+            start: range.start,
+            end: range.end,
+            text: text.into(),
+        }
+    }
+}
+
+impl MessageRevision {
+    /// Creates a revision replacing the content of a message
+    pub fn replace(message_id: impl Into<MessageId>, content: impl Into<String>) -> Self {
+        Self {
+            message_id: message_id.into(),
+            content: Some(content.into()),
+            edits: Vec::new(),
+        }
+    }
+
+    /// Creates a revision editing the content of a message
+    pub fn edit(message_id: impl Into<MessageId>, edits: Vec<TextEdit>) -> Self {
+        Self {
+            message_id: message_id.into(),
+            content: None,
+            edits,
+        }
+    }
+
+    /// Returns the revised content of a message with the given content, or `None` if an edit
+    /// does not fit the content.
+    pub fn apply(&self, content: &str) -> Option<String> {
+        if let Some(content) = &self.content {
+            return Some(content.clone());
+        }
+
+        let mut revised: Vec<char> = content.chars().collect();
+        for edit in &self.edits {
+            if edit.start > edit.end || edit.end > revised.len() {
+                return None;
+            }
+            revised.splice(edit.start..edit.end, edit.text.chars());
+        }
+        Some(revised.into_iter().collect())
+    }
+
+    /// Returns where a range of the content, such as that of a [`Citation`], lies in the revised
+    /// content, or `None` if the revision touches it.
+    ///
+    /// Ranges before an edit are kept, ranges after it are shifted by the change in length, and
+    /// ranges overlapping it, or any range when the whole content is replaced, are dropped.
+    ///
+    /// [`Citation`]: crate::extensions::Citation
+    pub fn revise_range(&self, range: Range<usize>) -> Option<Range<usize>> {
+        if self.content.is_some() {
+            return None;
+        }
+
+        self.edits.iter().try_fold(range, |range, edit| {
+            if range.end <= edit.start {
+                Some(range)
+            } else if range.start >= edit.end {
+                let inserted = edit.text.chars().count();
+                let shift = |offset: usize| offset - (edit.end - edit.start) + inserted;
+                Some(shift(range.start)..shift(range.end))
+            } else {
+                None
+            }
+        })
+    }
+}
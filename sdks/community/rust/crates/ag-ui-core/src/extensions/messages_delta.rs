@@ -0,0 +1,20 @@
+use json_patch::Patch;
+use serde::{Deserialize, Serialize};
+
+/// Changes to the messages of a thread since the last snapshot, sent as a `MESSAGES_DELTA`
+/// custom event.
+///
+/// The delta is a JSON Patch (RFC 6902) over the array of messages, like the delta of a
+/// `STATE_DELTA` event, so that only added and changed messages have to be sent for long
+/// threads. Clients should apply it to the messages it touches rather than to the whole array,
+/// so that its cost does not grow with the length of the thread.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessagesDelta {
+    pub delta: Patch,
+}
+
+impl MessagesDelta {
+    pub fn new(delta: Patch) -> Self {
+        Self { delta }
+    }
+}
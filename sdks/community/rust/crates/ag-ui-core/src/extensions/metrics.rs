@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+/// Usage reported by an agent for a model call, sent as a `METRICS` custom event.
+///
+/// All fields are optional, as providers do not report the same figures.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metrics {
+    /// The model that was called
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_tokens: Option<u64>,
+    /// How long the call took, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+impl Metrics {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: Some(model.into()),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_tokens(mut self, input_tokens: u64, output_tokens: u64) -> Self {
+        self.input_tokens = Some(input_tokens);
+        self.output_tokens = Some(output_tokens);
+        self
+    }
+
+    pub fn with_latency_ms(mut self, latency_ms: u64) -> Self {
+        self.latency_ms = Some(latency_ms);
+        self
+    }
+}
+
+/// The [`Metrics`] of a run summed over its model calls.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunMetrics {
+    /// The number of metrics recorded
+    pub calls: u32,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub latency_ms: u64,
+    /// The models called, in the order they were first reported
+    pub models: Vec<String>,
+}
+
+impl RunMetrics {
+    /// Adds the metrics of a model call. Figures that were not reported count as zero.
+    pub fn record(&mut self, metrics: &Metrics) {
+        self.calls += 1;
+        self.input_tokens += metrics.input_tokens.unwrap_or_default();
+        self.output_tokens += metrics.output_tokens.unwrap_or_default();
+        self.latency_ms += metrics.latency_ms.unwrap_or_default();
+        if let Some(model) = &metrics.model
+            && !self.models.contains(model)
+        {
+            self.models.push(model.clone());
+        }
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+}
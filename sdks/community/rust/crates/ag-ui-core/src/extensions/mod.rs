@@ -0,0 +1,77 @@
+//! Conventions layered on top of [`CustomEvent`]s.
+//!
+//! Each extension defines a typed payload that is carried in the `value` of a custom event with
+//! a well-known name, so that agents and clients agree on its shape without changes to the core
+//! protocol.
+
+mod audio;
+mod citation;
+mod message_revision;
+mod messages_delta;
+mod metrics;
+mod retry;
+mod tool_result;
+
+pub use audio::*;
+pub use citation::*;
+pub use message_revision::*;
+pub use messages_delta::*;
+pub use metrics::*;
+pub use retry::*;
+pub use tool_result::*;
+
+use crate::event::{CustomEvent, Event};
+use crate::state::AgentState;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// A typed payload carried by a [`CustomEvent`] with the name [`CustomEventExtension::NAME`].
+pub trait CustomEventExtension: Serialize + DeserializeOwned {
+    /// The name of the custom event carrying this payload
+    const NAME: &'static str;
+
+    /// Wraps the payload in a custom event
+    fn into_custom_event(self) -> Result<CustomEvent, serde_json::Error> {
+        Ok(CustomEvent::new(Self::NAME, serde_json::to_value(self)?))
+    }
+
+    /// Wraps the payload in an [`Event`]
+    fn into_event<StateT: AgentState>(self) -> Result<Event<StateT>, serde_json::Error> {
+        self.into_custom_event().map(Event::Custom)
+    }
+
+    /// Extracts the payload from a custom event.
+    ///
+    /// Returns `None` if the event is not of this extension, and an error if it is but its value
+    /// does not match the payload type.
+    fn from_custom_event(event: &CustomEvent) -> Option<Result<Self, serde_json::Error>> {
+        (event.name == Self::NAME).then(|| Self::deserialize(&event.value))
+    }
+}
+
+/// Implements [`CustomEventExtension`] for the extensions of this crate and lists their names in
+/// [`EXTENSION_NAMES`], so that an extension cannot be added without being listed.
+macro_rules! extensions {
+    ($($extension:ident => $name:literal,)*) => {
+        $(
+            impl CustomEventExtension for $extension {
+                const NAME: &'static str = $name;
+            }
+        )*
+
+        /// Names of the [`CustomEventExtension`]s of this crate
+        pub const EXTENSION_NAMES: &[&str] = &[$($name,)*];
+    };
+}
+
+extensions! {
+    AudioStart => "AUDIO_START",
+    AudioChunk => "AUDIO_CHUNK",
+    AudioEnd => "AUDIO_END",
+    Citation => "CITATION",
+    MessageRevision => "MESSAGE_REVISION",
+    MessagesDelta => "MESSAGES_DELTA",
+    Metrics => "METRICS",
+    Retry => "RETRY",
+    ToolResultDelta => "TOOL_RESULT_DELTA",
+}
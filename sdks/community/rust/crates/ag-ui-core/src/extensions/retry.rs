@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// Notice that an event stream failed with a retryable error and is being re-established.
+///
+/// Sent as a `RETRY` custom event before each new attempt. Events that the new attempt replays
+/// are not delivered again, so consumers see every event once.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Retry {
+    /// The number of the new attempt, starting at 1 for the first retry
+    pub attempt: u32,
+    /// How long is waited before the new attempt, in milliseconds
+    #[serde(rename = "delayMs")]
+    pub delay_ms: u64,
+    /// The error that ended the previous attempt
+    pub error: String,
+}
@@ -0,0 +1,23 @@
+use crate::types::ToolCallId;
+use serde::{Deserialize, Serialize};
+
+/// A piece of incremental output of a long-running tool, such as build logs or search progress.
+///
+/// Sent as a `TOOL_RESULT_DELTA` custom event. Any number of deltas may be sent for a tool call
+/// before the [`ToolCallResultEvent`](crate::event::ToolCallResultEvent) with the same tool call
+/// ID, which carries the final result and closes the stream of deltas.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolResultDelta {
+    #[serde(rename = "toolCallId")]
+    pub tool_call_id: ToolCallId,
+    pub delta: String,
+}
+
+impl ToolResultDelta {
+    pub fn new(tool_call_id: impl Into<ToolCallId>, delta: impl Into<String>) -> Self {
+        Self {
+            tool_call_id: tool_call_id.into(),
+            delta: delta.into(),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Generative UI: components rendered by the client, described by the agent through tool calls.
+//!
+//! A component is declared to the model as a tool whose parameters are the component's props.
+//! When the agent calls it, the client renders the component with the arguments of the call.
+
+use crate::event::Event;
+use crate::llm::tool_call_events;
+use crate::state::AgentState;
+use crate::types::{FunctionCall, MessageId, Tool, ToolCall, ToolCallId};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value as JsonValue;
+
+/// The props of a component the client can render.
+pub trait UiComponent: Serialize + DeserializeOwned {
+    /// The name of the tool rendering the component
+    const NAME: &'static str;
+    /// A description of the component for the model
+    const DESCRIPTION: &'static str;
+
+    /// The JSON schema of the props
+    fn parameters() -> JsonValue;
+
+    /// The tool definition declaring the component
+    fn tool() -> Tool {
+        Tool::new(
+            Self::NAME.to_string(),
+            Self::DESCRIPTION.to_string(),
+            Self::parameters(),
+        )
+    }
+
+    /// Converts the props into the tool call events asking the client to render the component.
+    fn into_events<StateT: AgentState>(
+        self,
+        parent_message_id: Option<MessageId>,
+    ) -> Result<Vec<Event<StateT>>, serde_json::Error> {
+        let tool_call = ToolCall::new(
+            ToolCallId::random(),
+            FunctionCall {
+                name: Self::NAME.to_string(),
+                arguments: serde_json::to_string(&self)?,
+            },
+        );
+        Ok(tool_call_events(tool_call, parent_message_id))
+    }
+
+    /// Extracts the props from a tool call.
+    ///
+    /// Returns `None` if the tool call is not for this component, and an error if it is but its
+    /// arguments do not match the props.
+    fn from_tool_call(tool_call: &ToolCall) -> Option<Result<Self, serde_json::Error>> {
+        (tool_call.function.name == Self::NAME).then(|| tool_call.parse_args())
+    }
+}
@@ -0,0 +1,67 @@
+//! Reconciliation of the messages sent with a run against a stored thread history.
+//!
+//! Frontends resend the whole conversation with every run. An agent persisting its sessions can
+//! merge it into the stored history with [`reconcile_messages`], which matches messages by ID so
+//! that each message is kept once, and reports which messages are new or were edited.
+
+use crate::types::{Message, MessageId};
+use std::collections::HashMap;
+
+/// The result of [`reconcile_messages`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconciledMessages {
+    /// The canonical history: the stored messages, with edits applied in place, followed by the
+    /// new messages in the order they were received
+    pub messages: Vec<Message>,
+    /// IDs of the incoming messages that were not in the stored history
+    pub new: Vec<MessageId>,
+    /// IDs of the stored messages whose incoming version differs
+    pub changed: Vec<MessageId>,
+}
+
+impl ReconciledMessages {
+    /// Whether the incoming messages added to or edited the stored history
+    pub fn has_changes(&self) -> bool {
+        !self.new.is_empty() || !self.changed.is_empty()
+    }
+}
+
+/// Merges `incoming` messages into the `stored` history of a thread by message ID.
+///
+/// A message already in the history replaces the stored version if it differs, which is reported
+/// as an edit. Stored messages absent from `incoming`, such as ones the frontend never saw, are
+/// kept.
+pub fn reconcile_messages(stored: &[Message], incoming: &[Message]) -> ReconciledMessages {
+    let mut messages = stored.to_vec();
+    let mut positions: HashMap<MessageId, usize> = messages
+        .iter()
+        .enumerate()
+        .map(|(position, message)| (message.id().clone(), position))
+        .collect();
+    let mut new = Vec::new();
+    let mut changed = Vec::new();
+
+    for message in incoming {
+        match positions.get(message.id()) {
+            Some(&position) => {
+                if messages[position] != *message {
+                    messages[position] = message.clone();
+                    if position < stored.len() && !changed.contains(message.id()) {
+                        changed.push(message.id().clone());
+                    }
+                }
+            }
+            None => {
+                positions.insert(message.id().clone(), messages.len());
+                new.push(message.id().clone());
+                messages.push(message.clone());
+            }
+        }
+    }
+
+    ReconciledMessages {
+        messages,
+        new,
+        changed,
+    }
+}
@@ -1,12 +1,22 @@
 #![doc = include_str!("../README.md")]
 
+pub mod canonical;
 pub mod error;
 pub mod event;
+pub mod extensions;
+pub mod generative_ui;
+pub mod history;
+pub mod llm;
+pub mod partial_json;
+pub mod protocol;
 mod state;
 pub mod types;
 
 pub use error::{AgUiError, Result};
-pub use state::{AgentState, FwdProps};
+pub use state::{AgentState, FwdProps, StatePatcher};
 
 /// Re-export to ensure the same type is used
 pub use serde_json::Value as JsonValue;
+
+/// Re-export to ensure the same JSON Patch (RFC 6902) types are used
+pub use json_patch::{Patch, PatchOperation};
@@ -0,0 +1,48 @@
+//! Anthropic messages API tool use.
+
+use crate::types::{FunctionCall, Tool, ToolCall};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+/// An entry of the `tools` parameter of a messages request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnthropicTool {
+    pub name: String,
+    pub description: String,
+    pub input_schema: JsonValue,
+}
+
+impl From<&Tool> for AnthropicTool {
+    fn from(tool: &Tool) -> Self {
+        Self {
+            name: tool.name.clone(),
+            description: tool.description.clone(),
+            input_schema: tool.parameters.clone(),
+        }
+    }
+}
+
+/// A `tool_use` content block of an assistant response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnthropicToolUse {
+    pub id: String,
+    pub name: String,
+    pub input: JsonValue,
+}
+
+impl From<AnthropicToolUse> for ToolCall {
+    fn from(tool_use: AnthropicToolUse) -> Self {
+        ToolCall::new(
+            tool_use.id,
+            FunctionCall {
+                name: tool_use.name,
+                arguments: tool_use.input.to_string(),
+            },
+        )
+    }
+}
+
+/// Converts tool definitions into the `tools` parameter of a messages request.
+pub fn tools(tools: &[Tool]) -> Vec<AnthropicTool> {
+    tools.iter().map(AnthropicTool::from).collect()
+}
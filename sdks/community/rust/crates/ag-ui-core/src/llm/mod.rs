@@ -0,0 +1,36 @@
+//! Conversions between AG-UI tools and the function-calling formats of LLM providers.
+//!
+//! Tools declared by the client in [`RunAgentInput::tools`](crate::types::RunAgentInput) can be
+//! passed to a model with [`openai::tools`] or [`anthropic::tools`], and the tool calls the model
+//! generates turned back into AG-UI events with [`tool_call_events`].
+
+pub mod anthropic;
+pub mod openai;
+
+use crate::event::{Event, ToolCallArgsEvent, ToolCallEndEvent, ToolCallStartEvent};
+use crate::state::AgentState;
+use crate::types::{MessageId, ToolCall};
+
+/// Converts a complete tool call into the `TOOL_CALL_START`, `TOOL_CALL_ARGS` and
+/// `TOOL_CALL_END` events announcing it to the client.
+///
+/// The arguments are sent in a single `TOOL_CALL_ARGS` event, which is omitted if they are empty.
+pub fn tool_call_events<StateT: AgentState>(
+    tool_call: ToolCall,
+    parent_message_id: Option<MessageId>,
+) -> Vec<Event<StateT>> {
+    let ToolCall { id, function, .. } = tool_call;
+
+    let mut start = ToolCallStartEvent::new(id.clone(), function.name);
+    start.parent_message_id = parent_message_id;
+
+    let mut events = vec![Event::ToolCallStart(start)];
+    if !function.arguments.is_empty() {
+        events.push(Event::ToolCallArgs(ToolCallArgsEvent::new(
+            id.clone(),
+            function.arguments,
+        )));
+    }
+    events.push(Event::ToolCallEnd(ToolCallEndEvent::new(id)));
+    events
+}
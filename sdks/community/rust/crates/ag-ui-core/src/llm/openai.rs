@@ -0,0 +1,44 @@
+//! OpenAI chat completions function calling.
+//!
+//! The `tool_calls` of an OpenAI assistant message already have the shape of a [`ToolCall`], so
+//! they can be deserialized into one directly.
+//!
+//! [`ToolCall`]: crate::types::ToolCall
+
+use crate::types::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+/// An entry of the `tools` parameter of a chat completions request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenAiTool {
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: OpenAiFunction,
+}
+
+/// The function definition of an [`OpenAiTool`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenAiFunction {
+    pub name: String,
+    pub description: String,
+    pub parameters: JsonValue,
+}
+
+impl From<&Tool> for OpenAiTool {
+    fn from(tool: &Tool) -> Self {
+        Self {
+            tool_type: "function".to_string(),
+            function: OpenAiFunction {
+                name: tool.name.clone(),
+                description: tool.description.clone(),
+                parameters: tool.parameters.clone(),
+            },
+        }
+    }
+}
+
+/// Converts tool definitions into the `tools` parameter of a chat completions request.
+pub fn tools(tools: &[Tool]) -> Vec<OpenAiTool> {
+    tools.iter().map(OpenAiTool::from).collect()
+}
@@ -0,0 +1,233 @@
+//! Best-effort parsing of JSON that is still being streamed, such as the arguments of a tool call
+//! arriving in `TOOL_CALL_ARGS` deltas.
+//!
+//! An incomplete object or array is completed by closing its open string and containers, after
+//! dropping whatever trailing token cannot be completed, such as a key without a value or a
+//! truncated literal. String values are thus visible as they grow, while numbers may still grow
+//! too. The document is scanned once, and the completed result parsed at most twice, so that
+//! parsing the whole document again after every delta stays linear in its length.
+
+use serde::de::DeserializeOwned;
+use serde_json::Value as JsonValue;
+
+/// Parses a possibly incomplete JSON object or array, returning `None` if it is not one, or if
+/// it cannot be completed into valid JSON.
+pub fn parse_partial(json: &str) -> Option<JsonValue> {
+    let scan = scan(json)?;
+    let complete = |end: usize, quote: bool| {
+        let mut completed = json[..end].to_string();
+        if quote {
+            completed.push('"');
+        }
+        completed.extend(scan.closers.iter().rev().map(|&closer| char::from(closer)));
+        serde_json::from_str(&completed).ok()
+    };
+    match scan.tail {
+        Tail::None => complete(scan.boundary, false),
+        Tail::String { end } => complete(end, true),
+        // The literal or number may be complete, or truncated
+        Tail::Scalar => complete(json.len(), false).or_else(|| complete(scan.boundary, false)),
+    }
+}
+
+/// What an open container expects next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    /// Its first key or value, or its end
+    First,
+    /// A key or value following a comma
+    Next,
+    /// The colon following a key
+    Colon,
+    /// The value following a colon
+    Value,
+    /// A comma or its end
+    CommaOrEnd,
+}
+
+/// How a partial document ends after its last boundary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tail {
+    /// Nothing that can be kept
+    None,
+    /// Inside a string value, which can be closed after `end`, before any truncated escape
+    String { end: usize },
+    /// With a literal or number
+    Scalar,
+}
+
+/// The structure of a partial document
+#[derive(Debug)]
+struct Scan {
+    /// The closing brackets of the containers still open, innermost last
+    closers: Vec<u8>,
+    /// The end of the last complete token, where the document can be cut. No bracket follows
+    /// it, so the same containers are open there.
+    boundary: usize,
+    tail: Tail,
+}
+
+/// Scans the structure of a partial object or array, returning `None` if it is invalid
+fn scan(json: &str) -> Option<Scan> {
+    let bytes = json.as_bytes();
+    // The closing bracket of each open container, and what it expects next
+    let mut open: Vec<(u8, Expect)> = Vec::new();
+    let mut started = false;
+    let mut boundary = 0;
+    let mut i = 0;
+    let scan = |open: Vec<(u8, Expect)>, boundary, tail| {
+        Some(Scan {
+            closers: open.into_iter().map(|(closer, _)| closer).collect(),
+            boundary,
+            tail,
+        })
+    };
+
+    while let Some(&c) = bytes.get(i) {
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        if open.is_empty() && (started || !matches!(c, b'{' | b'[')) {
+            return None;
+        }
+        started = true;
+        let expects_value = match open.last() {
+            None => true,
+            Some((b']', expect)) => matches!(expect, Expect::First | Expect::Next),
+            Some((_, expect)) => *expect == Expect::Value,
+        };
+        match c {
+            b'{' | b'[' if expects_value => {
+                open.push((if c == b'{' { b'}' } else { b']' }, Expect::First));
+                i += 1;
+                boundary = i;
+                continue;
+            }
+            b'}' | b']' => match open.pop()? {
+                (closer, Expect::First | Expect::CommaOrEnd) if closer == c => i += 1,
+                _ => return None,
+            },
+            b',' => match open.last_mut()? {
+                (_, expect @ Expect::CommaOrEnd) => {
+                    *expect = Expect::Next;
+                    i += 1;
+                    continue;
+                }
+                _ => return None,
+            },
+            b':' => match open.last_mut()? {
+                (b'}', expect @ Expect::Colon) => {
+                    *expect = Expect::Value;
+                    i += 1;
+                    continue;
+                }
+                _ => return None,
+            },
+            b'"' => {
+                let key = !expects_value;
+                if key && !matches!(open.last(), Some((b'}', Expect::First | Expect::Next))) {
+                    return None;
+                }
+                i += 1;
+                // Find the closing quote, or where the string is truncated
+                let truncated = loop {
+                    match bytes.get(i) {
+                        Some(b'"') => break None,
+                        Some(b'\\') => {
+                            let len = if bytes.get(i + 1) == Some(&b'u') {
+                                6
+                            } else {
+                                2
+                            };
+                            if i + len > bytes.len() {
+                                break Some(i);
+                            }
+                            i += len;
+                        }
+                        Some(_) => i += 1,
+                        None => break Some(i),
+                    }
+                };
+                if let Some(end) = truncated {
+                    let tail = if key {
+                        Tail::None
+                    } else {
+                        Tail::String { end }
+                    };
+                    return scan(open, boundary, tail);
+                }
+                i += 1;
+                if key {
+                    if let Some((_, expect)) = open.last_mut() {
+                        *expect = Expect::Colon;
+                    }
+                    continue;
+                }
+            }
+            b'{' | b'[' => return None,
+            _ if expects_value => {
+                while bytes
+                    .get(i)
+                    .is_some_and(|c| !c.is_ascii_whitespace() && !b"{}[],:\"".contains(c))
+                {
+                    i += 1;
+                }
+                if i == bytes.len() {
+                    return scan(open, boundary, Tail::Scalar);
+                }
+            }
+            _ => return None,
+        }
+        // A value just ended
+        if let Some((_, expect)) = open.last_mut() {
+            *expect = Expect::CommaOrEnd;
+        }
+        boundary = i;
+    }
+
+    if !started {
+        return None;
+    }
+    scan(open, boundary, Tail::None)
+}
+
+/// Accumulates the deltas of a streamed JSON document, such as the arguments of a tool call.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PartialJson {
+    buffer: String,
+}
+
+impl PartialJson {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a delta to the document
+    pub fn push(&mut self, delta: &str) {
+        self.buffer.push_str(delta);
+    }
+
+    /// The document received so far
+    pub fn as_str(&self) -> &str {
+        &self.buffer
+    }
+
+    /// The document received so far, completed into a value, see [`parse_partial`]
+    pub fn value(&self) -> Option<JsonValue> {
+        parse_partial(&self.buffer)
+    }
+
+    /// The document received so far, completed and deserialized into `T`.
+    ///
+    /// Fields that have not been received yet are missing, so the fields of `T` should be
+    /// optional or have defaults for this to succeed before the document is complete.
+    pub fn parse<T: DeserializeOwned>(&self) -> Option<T> {
+        serde_json::from_value(self.value()?).ok()
+    }
+
+    /// Deserializes the complete document into `T`, failing if it is incomplete or invalid.
+    pub fn finish<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_str(&self.buffer)
+    }
+}
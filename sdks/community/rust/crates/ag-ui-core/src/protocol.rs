@@ -0,0 +1,42 @@
+//! Machine-readable description of the parts of the AG-UI protocol implemented by this crate,
+//! so that agents, clients and tooling can compare what they support.
+
+use crate::event::EventType;
+use crate::extensions::EXTENSION_NAMES;
+use serde::Serialize;
+
+/// The capabilities of this crate, see [`Capabilities`]
+pub const CAPABILITIES: Capabilities = Capabilities {
+    event_types: EventType::ALL,
+    custom_events: EXTENSION_NAMES,
+    content_types: &["text/event-stream"],
+};
+
+/// The event types, custom event extensions and content types supported by an implementation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    /// Event types that can be (de)serialized
+    #[serde(rename = "eventTypes")]
+    pub event_types: &'static [EventType],
+    /// Names of the [`CustomEventExtension`](crate::extensions::CustomEventExtension)s with a
+    /// typed payload
+    #[serde(rename = "customEvents")]
+    pub custom_events: &'static [&'static str],
+    /// Content types of the encodings of an event stream
+    #[serde(rename = "contentTypes")]
+    pub content_types: &'static [&'static str],
+}
+
+impl Capabilities {
+    pub fn supports_event_type(&self, event_type: EventType) -> bool {
+        self.event_types.contains(&event_type)
+    }
+
+    pub fn supports_custom_event(&self, name: &str) -> bool {
+        self.custom_events.contains(&name)
+    }
+
+    pub fn supports_content_type(&self, content_type: &str) -> bool {
+        self.content_types.contains(&content_type)
+    }
+}
@@ -1,3 +1,5 @@
+use crate::Patch;
+use crate::error::{AgUiError, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use std::fmt::Debug;
@@ -6,9 +8,25 @@ use std::fmt::Debug;
 pub trait AgentState:
     'static + Debug + Clone + Send + Sync + for<'de> Deserialize<'de> + Serialize + Default
 {
+    /// Applies a JSON Patch to the state, leaving it as it was on failure. Called by
+    /// [`StatePatcher::apply`].
+    ///
+    /// By default the patch is applied to the JSON form of the state kept in `patcher`, and the
+    /// state deserialized from it. States that can be patched in place override this.
+    fn apply_patch(&mut self, patch: &Patch, patcher: &mut StatePatcher) -> Result<()> {
+        *self = patcher.apply_to_json(self, patch)?;
+        Ok(())
+    }
+}
+
+impl AgentState for JsonValue {
+    /// Patches the state in place, without keeping another copy of it
+    fn apply_patch(&mut self, patch: &Patch, _patcher: &mut StatePatcher) -> Result<()> {
+        json_patch::patch(self, patch)
+            .map_err(|err| AgUiError::new(format!("Failed to apply state patch: {err}")))
+    }
 }
 
-impl AgentState for JsonValue {}
 impl AgentState for () {}
 
 /// Trait bounds for forwarded props
@@ -19,3 +37,56 @@ pub trait FwdProps:
 
 impl FwdProps for JsonValue {}
 impl FwdProps for () {}
+
+/// Applies JSON Patches to a state.
+///
+/// A [`JsonValue`] state is patched in place. Other states are patched through their JSON form,
+/// which the patcher keeps between patches, so that only the first patch after
+/// [`StatePatcher::reset`] serializes the whole state. Reset the patcher whenever the state is
+/// changed by other means, such as a snapshot.
+///
+/// Every patch of such a state still deserializes the whole state from its JSON form, as the
+/// state is expected to be up to date after each event, and the JSON form is a second copy of
+/// the state held by the patcher. Large typed states that receive many deltas pay for both.
+#[derive(Debug, Clone, Default)]
+pub struct StatePatcher {
+    json: Option<JsonValue>,
+}
+
+impl StatePatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forgets the JSON form of the state, after the state was changed by other means
+    pub fn reset(&mut self) {
+        self.json = None;
+    }
+
+    /// Applies `patch` to `state`, leaving it as it was on failure.
+    ///
+    /// `state` must be the result of the previous call, unless the patcher was reset since.
+    pub fn apply<StateT: AgentState>(&mut self, state: &mut StateT, patch: &Patch) -> Result<()> {
+        state.apply_patch(patch, self)
+    }
+
+    /// Returns `state` with `patch` applied to its JSON form
+    fn apply_to_json<StateT: AgentState>(
+        &mut self,
+        state: &StateT,
+        patch: &Patch,
+    ) -> Result<StateT> {
+        let json = match &mut self.json {
+            Some(json) => json,
+            json => json.insert(serde_json::to_value(state)?),
+        };
+        json_patch::patch(json, patch)
+            .map_err(|err| AgUiError::new(format!("Failed to apply state patch: {err}")))?;
+
+        StateT::deserialize(&*json).map_err(|err| {
+            // The patched JSON no longer matches `state`
+            self.json = None;
+            AgUiError::from(err)
+        })
+    }
+}
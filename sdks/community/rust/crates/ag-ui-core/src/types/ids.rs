@@ -85,7 +85,7 @@ define_id_type!(MessageId);
 
 /// A tool call ID.
 /// Used by some providers to denote a specific ID for a tool call generation, where the result of the tool call must also use this ID.
-#[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Deserialize, Serialize, Clone)]
 pub struct ToolCallId(String);
 
 /// Tool Call ID
@@ -93,12 +93,132 @@ pub struct ToolCallId(String);
 /// Does not follow UUID format, instead uses "call_xxxxxxxx"
 impl ToolCallId {
     pub fn random() -> Self {
-        let uuid = &Uuid::new_v4().to_string()[..8];
-        let id = format!("call_{uuid}");
+        Self::with_prefix("call_")
+    }
+
+    /// Creates a random ID in the style of OpenAI: `call_` followed by 24 alphanumeric characters.
+    pub fn openai() -> Self {
+        Self(format!("call_{}", random_suffix(24)))
+    }
+
+    /// Creates a random ID in the style of Anthropic: `toolu_` followed by 24 alphanumeric
+    /// characters.
+    pub fn anthropic() -> Self {
+        Self(format!("toolu_{}", random_suffix(24)))
+    }
+
+    /// Creates a random ID consisting of a hyphenated UUID, as used by Gemini based agents.
+    pub fn uuid() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+
+    /// Creates a random ID consisting of `prefix` followed by 8 alphanumeric characters.
+    pub fn with_prefix(prefix: &str) -> Self {
+        Self(format!("{prefix}{}", random_suffix(8)))
+    }
+
+    /// Checks that the ID has the given format, for backends that reject IDs of other providers.
+    pub fn validate(&self, format: &ToolCallIdFormat) -> crate::error::Result<()> {
+        if format.matches(&self.0) {
+            Ok(())
+        } else {
+            Err(crate::error::AgUiError::new(format!(
+                "Tool call ID '{}' does not have the {format:?} format",
+                self.0
+            )))
+        }
+    }
+}
+
+/// Returns `len` random alphanumeric characters, at most 32.
+fn random_suffix(len: usize) -> String {
+    Uuid::new_v4().simple().to_string()[..len].to_string()
+}
+
+/// The format of the tool call IDs generated by a provider.
+///
+/// Bridges can generate the IDs their backend expects with [`ToolCallIdFormat::generate`], and
+/// check IDs received from elsewhere with [`ToolCallId::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolCallIdFormat {
+    /// `call_` followed by alphanumeric characters, see [`ToolCallId::openai`]
+    OpenAi,
+    /// `toolu_` followed by alphanumeric characters, see [`ToolCallId::anthropic`]
+    Anthropic,
+    /// A UUID, see [`ToolCallId::uuid`]
+    Uuid,
+    /// The given prefix followed by alphanumeric characters, see [`ToolCallId::with_prefix`]
+    Prefixed(String),
+}
+
+impl ToolCallIdFormat {
+    /// Creates a random ID of this format.
+    pub fn generate(&self) -> ToolCallId {
+        match self {
+            Self::OpenAi => ToolCallId::openai(),
+            Self::Anthropic => ToolCallId::anthropic(),
+            Self::Uuid => ToolCallId::uuid(),
+            Self::Prefixed(prefix) => ToolCallId::with_prefix(prefix),
+        }
+    }
+
+    /// Whether the ID has this format.
+    pub fn matches(&self, id: &str) -> bool {
+        let prefixed = |prefix: &str| {
+            id.strip_prefix(prefix).is_some_and(|suffix| {
+                !suffix.is_empty() && suffix.chars().all(|c| c.is_ascii_alphanumeric())
+            })
+        };
+        match self {
+            Self::OpenAi => prefixed("call_"),
+            Self::Anthropic => prefixed("toolu_"),
+            Self::Uuid => Uuid::parse_str(id).is_ok(),
+            Self::Prefixed(prefix) => prefixed(prefix),
+        }
+    }
+}
+
+/// Allows creating a tool call ID from one generated by a provider.
+impl From<String> for ToolCallId {
+    fn from(id: String) -> Self {
         Self(id)
     }
 }
 
+/// Allows creating a tool call ID from one generated by a provider.
+impl From<&str> for ToolCallId {
+    fn from(id: &str) -> Self {
+        Self(id.to_string())
+    }
+}
+
+/// Allows creating a tool call ID in the [`ToolCallIdFormat::Uuid`] format from a Uuid.
+impl From<Uuid> for ToolCallId {
+    fn from(uuid: Uuid) -> Self {
+        Self(uuid.to_string())
+    }
+}
+
+/// Allows passing the ID back to a provider.
+impl From<ToolCallId> for String {
+    fn from(id: ToolCallId) -> Self {
+        id.0
+    }
+}
+
+impl AsRef<str> for ToolCallId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Allows printing the ID.
+impl std::fmt::Display for ToolCallId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
 impl Deref for ToolCallId {
     type Target = str;
     fn deref(&self) -> &Self::Target {
@@ -116,4 +236,30 @@ mod tests {
         assert!(id.0.starts_with("call_"));
         dbg!(id);
     }
+
+    #[test]
+    fn test_tool_call_id_formats() {
+        use super::{ToolCallId, ToolCallIdFormat};
+
+        let formats = [
+            ToolCallIdFormat::OpenAi,
+            ToolCallIdFormat::Anthropic,
+            ToolCallIdFormat::Uuid,
+            ToolCallIdFormat::Prefixed("adk-".to_string()),
+        ];
+        for format in &formats {
+            let id = format.generate();
+            assert!(id.validate(format).is_ok(), "{id} is not {format:?}");
+            // No format accepts the IDs of another
+            for other in formats.iter().filter(|other| *other != format) {
+                assert!(id.validate(other).is_err(), "{id} is {other:?}");
+            }
+        }
+
+        assert_eq!(ToolCallId::openai().len(), 5 + 24);
+        assert!(ToolCallIdFormat::OpenAi.matches(&ToolCallId::random()));
+        assert!(ToolCallIdFormat::Anthropic.matches("toolu_01A09q90qw90lq917835lq9"));
+        assert!(!ToolCallIdFormat::Anthropic.matches("toolu_"));
+        assert!(!ToolCallIdFormat::OpenAi.matches("call_a-b"));
+    }
 }
@@ -1,6 +1,6 @@
 use crate::JsonValue;
 use crate::types::context::Context;
-use crate::types::ids::{RunId, ThreadId};
+use crate::types::ids::{MessageId, RunId, ThreadId};
 use crate::types::message::Message;
 use crate::types::tool::Tool;
 use serde::{Deserialize, Serialize};
@@ -41,3 +41,60 @@ impl<StateT, FwdPropsT> RunAgentInput<StateT, FwdPropsT> {
         }
     }
 }
+
+/// Name of the forwarded prop asking the agent to plan without executing side-effecting tools.
+///
+/// In a dry run the agent may still emit thinking, steps and tool calls, but should answer tool
+/// calls with synthetic results instead of running them.
+pub const DRY_RUN_PROP: &str = "dryRun";
+
+impl<StateT> RunAgentInput<StateT, JsonValue> {
+    /// Whether the client asked for a dry run, see [`DRY_RUN_PROP`]
+    pub fn is_dry_run(&self) -> bool {
+        self.forwarded_props
+            .get(DRY_RUN_PROP)
+            .and_then(JsonValue::as_bool)
+            .unwrap_or(false)
+    }
+
+    /// The regeneration the client asked for, see [`REGENERATE_PROP`]
+    pub fn regeneration(&self) -> Option<Regeneration> {
+        serde_json::from_value(self.forwarded_props.get(REGENERATE_PROP)?.clone()).ok()
+    }
+
+    /// Whether the client asked to regenerate responses, see [`REGENERATE_PROP`]
+    pub fn is_regeneration(&self) -> bool {
+        self.regeneration().is_some()
+    }
+}
+
+/// Name of the forwarded prop asking the agent to regenerate its responses, holding a
+/// [`Regeneration`].
+///
+/// The client truncates the conversation after the given message before sending it, so an agent
+/// keeping its own history should drop the replaced messages with [`Regeneration::truncate`]
+/// rather than appending the new responses after them.
+pub const REGENERATE_PROP: &str = "regenerate";
+
+/// A request to regenerate the responses following a message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Regeneration {
+    /// The last message kept in the conversation
+    pub after_message_id: MessageId,
+    /// The messages that followed it, which the regenerated responses replace
+    pub replaced_message_ids: Vec<MessageId>,
+}
+
+impl Regeneration {
+    /// Removes the messages following [`Regeneration::after_message_id`] from a stored history.
+    /// A history without that message is left unchanged.
+    pub fn truncate(&self, history: &mut Vec<Message>) {
+        if let Some(position) = history
+            .iter()
+            .position(|message| *message.id() == self.after_message_id)
+        {
+            history.truncate(position + 1);
+        }
+    }
+}
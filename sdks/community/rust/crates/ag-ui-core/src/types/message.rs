@@ -1,6 +1,10 @@
 use crate::types::ids::{MessageId, ToolCallId};
 use crate::types::tool::ToolCall;
-use serde::{Deserialize, Serialize};
+use serde::de::{DeserializeSeed, Error as _, IgnoredAny, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value as JsonValue;
+use std::fmt;
 
 /// A generated function call from a model
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -13,12 +17,30 @@ pub struct FunctionCall {
 /// Message role.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
+#[non_exhaustive]
 pub enum Role {
     Developer,
     System,
     Assistant,
     User,
     Tool,
+    /// A role outside of the protocol, such as `"function"` or `"critic"`, kept as received
+    #[serde(untagged)]
+    Other(String),
+}
+
+/// Parses a role, falling back to [`Role::Other`] for roles outside of the protocol
+impl From<&str> for Role {
+    fn from(role: &str) -> Self {
+        match role {
+            "developer" => Role::Developer,
+            "system" => Role::System,
+            "assistant" => Role::Assistant,
+            "user" => Role::User,
+            "tool" => Role::Tool,
+            role => Role::Other(role.to_string()),
+        }
+    }
 }
 
 // Utility methods for serde defaults
@@ -49,6 +71,8 @@ pub struct BaseMessage {
     pub content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<JsonValue>,
 }
 
 /// A developer message.
@@ -61,6 +85,8 @@ pub struct DeveloperMessage {
     pub content: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<JsonValue>,
 }
 
 impl DeveloperMessage {
@@ -70,6 +96,7 @@ impl DeveloperMessage {
             role: Role::Developer,
             content,
             name: None,
+            metadata: None,
         }
     }
 
@@ -77,6 +104,11 @@ impl DeveloperMessage {
         self.name = Some(name);
         self
     }
+
+    pub fn with_metadata(mut self, metadata: JsonValue) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
 }
 
 /// A system message. This is usually where the system prompt goes.
@@ -88,6 +120,8 @@ pub struct SystemMessage {
     pub content: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<JsonValue>,
 }
 
 impl SystemMessage {
@@ -97,6 +131,7 @@ impl SystemMessage {
             role: Role::System,
             content,
             name: None,
+            metadata: None,
         }
     }
 
@@ -104,6 +139,11 @@ impl SystemMessage {
         self.name = Some(name);
         self
     }
+
+    pub fn with_metadata(mut self, metadata: JsonValue) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
 }
 
 /// An assistant message (ie, from the model).
@@ -118,6 +158,8 @@ pub struct AssistantMessage {
     pub name: Option<String>,
     #[serde(rename = "toolCalls", skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<JsonValue>,
 }
 
 impl AssistantMessage {
@@ -128,6 +170,7 @@ impl AssistantMessage {
             content: None,
             name: None,
             tool_calls: None,
+            metadata: None,
         }
     }
 
@@ -145,6 +188,11 @@ impl AssistantMessage {
         self.tool_calls = Some(tool_calls);
         self
     }
+
+    pub fn with_metadata(mut self, metadata: JsonValue) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
 }
 
 /// A user message.
@@ -156,6 +204,8 @@ pub struct UserMessage {
     pub content: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<JsonValue>,
 }
 
 impl UserMessage {
@@ -165,6 +215,7 @@ impl UserMessage {
             role: Role::User,
             content,
             name: None,
+            metadata: None,
         }
     }
 
@@ -172,6 +223,11 @@ impl UserMessage {
         self.name = Some(name);
         self
     }
+
+    pub fn with_metadata(mut self, metadata: JsonValue) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
 }
 
 /// A tool call result.
@@ -185,6 +241,8 @@ pub struct ToolMessage {
     pub tool_call_id: ToolCallId,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<JsonValue>,
 }
 
 impl ToolMessage {
@@ -199,6 +257,7 @@ impl ToolMessage {
             role: Role::Tool,
             tool_call_id: tool_call_id.into(),
             error: None,
+            metadata: None,
         }
     }
 
@@ -206,23 +265,32 @@ impl ToolMessage {
         self.error = Some(error);
         self
     }
+
+    pub fn with_metadata(mut self, metadata: JsonValue) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
 }
 
 /// Represents the different type of messages that you might receive, but as an enum.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(tag = "role", rename_all = "lowercase")]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "role", rename_all = "lowercase", remote = "Self")]
 pub enum Message {
     Developer {
         id: MessageId,
         content: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        metadata: Option<JsonValue>,
     },
     System {
         id: MessageId,
         content: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        metadata: Option<JsonValue>,
     },
     Assistant {
         id: MessageId,
@@ -232,12 +300,16 @@ pub enum Message {
         name: Option<String>,
         #[serde(rename = "toolCalls", skip_serializing_if = "Option::is_none")]
         tool_calls: Option<Vec<ToolCall>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        metadata: Option<JsonValue>,
     },
     User {
         id: MessageId,
         content: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        metadata: Option<JsonValue>,
     },
     Tool {
         id: MessageId,
@@ -246,38 +318,316 @@ pub enum Message {
         tool_call_id: ToolCallId,
         #[serde(skip_serializing_if = "Option::is_none")]
         error: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        metadata: Option<JsonValue>,
     },
+    /// A message with a role outside of the protocol. Fields other than the ones below, and a
+    /// `content` or `metadata` that is null or not of the expected type, are kept in `extra`, so
+    /// that the message is serialized back to equivalent JSON: the fields below first, then
+    /// those of `extra` in its order.
+    #[serde(skip)]
+    Other {
+        role: String,
+        id: MessageId,
+        content: Option<String>,
+        metadata: Option<JsonValue>,
+        extra: serde_json::Map<String, JsonValue>,
+    },
+}
+
+impl Serialize for Message {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Message::Other {
+            role,
+            id,
+            content,
+            metadata,
+            extra,
+        } = self
+        else {
+            return Self::serialize(self, serializer);
+        };
+
+        // Written in a fixed order, whether or not `serde_json::Map` preserves insertion order
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("role", role)?;
+        map.serialize_entry("id", id)?;
+        if let Some(content) = content {
+            map.serialize_entry("content", content)?;
+        }
+        if let Some(metadata) = metadata {
+            map.serialize_entry("metadata", metadata)?;
+        }
+        for (key, value) in extra {
+            let written = match key.as_str() {
+                "role" | "id" => true,
+                "content" => content.is_some(),
+                "metadata" => metadata.is_some(),
+                _ => false,
+            };
+            if !written {
+                map.serialize_entry(key, value)?;
+            }
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Message {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(MessageVisitor)
+    }
+}
+
+/// The fields of the protocol, which may appear once in a message
+const MESSAGE_FIELDS: &[&str] = &[
+    "role",
+    "id",
+    "content",
+    "name",
+    "toolCalls",
+    "toolCallId",
+    "error",
+    "metadata",
+];
+
+/// Deserializes a message in a single pass, as the fields of its role.
+///
+/// Only the fields preceding the role, typically just the id, are buffered until the role is
+/// known.
+struct MessageVisitor;
+
+impl<'de> Visitor<'de> for MessageVisitor {
+    type Value = Message;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a message object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Message, A::Error> {
+        let mut buffered = Vec::new();
+        let mut fields = None;
+        let mut seen = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if let Some(field) = MESSAGE_FIELDS.iter().find(|field| **field == key) {
+                if seen.contains(field) {
+                    return Err(A::Error::duplicate_field(field));
+                }
+                seen.push(field);
+            }
+            match &mut fields {
+                Some(fields) => map.next_value_seed(MessageField { fields, key })?,
+                None if key == "role" => {
+                    let role = Role::from(map.next_value::<String>()?.as_str());
+                    let mut role_fields = MessageFields::new(role);
+                    for (key, value) in buffered.drain(..) {
+                        let field = MessageField {
+                            fields: &mut role_fields,
+                            key,
+                        };
+                        field.deserialize(value).map_err(A::Error::custom)?;
+                    }
+                    fields = Some(role_fields);
+                }
+                None => buffered.push((key, map.next_value::<JsonValue>()?)),
+            }
+        }
+        fields
+            .ok_or_else(|| A::Error::missing_field("role"))?
+            .into_message()
+    }
+}
+
+/// The fields of a message read so far
+struct MessageFields {
+    role: Role,
+    id: Option<MessageId>,
+    content: Option<String>,
+    name: Option<String>,
+    tool_calls: Option<Vec<ToolCall>>,
+    tool_call_id: Option<ToolCallId>,
+    error: Option<String>,
+    metadata: Option<JsonValue>,
+    /// The other fields of a message with a role outside of the protocol
+    extra: serde_json::Map<String, JsonValue>,
+}
+
+impl MessageFields {
+    fn new(role: Role) -> Self {
+        Self {
+            role,
+            id: None,
+            content: None,
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+            error: None,
+            metadata: None,
+            extra: serde_json::Map::new(),
+        }
+    }
+
+    fn into_message<E: serde::de::Error>(self) -> Result<Message, E> {
+        let MessageFields {
+            role,
+            id,
+            content,
+            name,
+            tool_calls,
+            tool_call_id,
+            error,
+            metadata,
+            extra,
+        } = self;
+        let id = id.ok_or_else(|| E::missing_field("id"))?;
+        let required = |content: Option<String>| content.ok_or_else(|| E::missing_field("content"));
+        Ok(match role {
+            Role::Developer => Message::Developer {
+                id,
+                content: required(content)?,
+                name,
+                metadata,
+            },
+            Role::System => Message::System {
+                id,
+                content: required(content)?,
+                name,
+                metadata,
+            },
+            Role::Assistant => Message::Assistant {
+                id,
+                content,
+                name,
+                tool_calls,
+                metadata,
+            },
+            Role::User => Message::User {
+                id,
+                content: required(content)?,
+                name,
+                metadata,
+            },
+            Role::Tool => Message::Tool {
+                id,
+                content: required(content)?,
+                tool_call_id: tool_call_id.ok_or_else(|| E::missing_field("toolCallId"))?,
+                error,
+                metadata,
+            },
+            Role::Other(role) => Message::Other {
+                role,
+                id,
+                content,
+                metadata,
+                extra,
+            },
+        })
+    }
+}
+
+/// Reads the value of a field into [`MessageFields`], with the type it has for the role
+struct MessageField<'a> {
+    fields: &'a mut MessageFields,
+    key: String,
+}
+
+impl<'de> DeserializeSeed<'de> for MessageField<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        let fields = self.fields;
+        let role = &fields.role;
+        match self.key.as_str() {
+            "id" => fields.id = Some(MessageId::deserialize(deserializer)?),
+            "content" | "metadata" if matches!(role, Role::Other(_)) => {
+                match (self.key.as_str(), JsonValue::deserialize(deserializer)?) {
+                    ("content", JsonValue::String(content)) => fields.content = Some(content),
+                    ("metadata", metadata) if !metadata.is_null() => {
+                        fields.metadata = Some(metadata)
+                    }
+                    // Null content or metadata, and content of another shape such as multimodal
+                    // parts, are kept as is
+                    (_, value) => {
+                        fields.extra.insert(self.key, value);
+                    }
+                }
+            }
+            "metadata" => fields.metadata = Option::deserialize(deserializer)?,
+            "content" => fields.content = Option::deserialize(deserializer)?,
+            _ if matches!(role, Role::Other(_)) => {
+                if fields.extra.contains_key(&self.key) {
+                    return Err(D::Error::custom(format_args!(
+                        "duplicate field `{}`",
+                        self.key
+                    )));
+                }
+                fields
+                    .extra
+                    .insert(self.key, JsonValue::deserialize(deserializer)?);
+            }
+            "name" if *role != Role::Tool => fields.name = Option::deserialize(deserializer)?,
+            "toolCalls" if *role == Role::Assistant => {
+                fields.tool_calls = Option::deserialize(deserializer)?
+            }
+            "toolCallId" if *role == Role::Tool => {
+                fields.tool_call_id = Some(ToolCallId::deserialize(deserializer)?)
+            }
+            "error" if *role == Role::Tool => fields.error = Option::deserialize(deserializer)?,
+            _ => {
+                IgnoredAny::deserialize(deserializer)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Message {
+    /// Creates a message of the given role. A [`Role::Other`] naming a role of the protocol, such
+    /// as `"user"`, creates a message of that role, as it would be read back once serialized.
     pub fn new<S: AsRef<str>>(role: Role, id: impl Into<MessageId>, content: S) -> Self {
+        let role = match role {
+            Role::Other(role) => Role::from(role.as_str()),
+            role => role,
+        };
         match role {
             Role::Developer => Self::Developer {
                 id: id.into(),
                 content: content.as_ref().to_string(),
                 name: None,
+                metadata: None,
             },
             Role::System => Self::System {
                 id: id.into(),
                 content: content.as_ref().to_string(),
                 name: None,
+                metadata: None,
             },
             Role::Assistant => Self::Assistant {
                 id: id.into(),
                 content: Some(content.as_ref().to_string()),
                 name: None,
                 tool_calls: None,
+                metadata: None,
             },
             Role::User => Self::User {
                 id: id.into(),
                 content: content.as_ref().to_string(),
                 name: None,
+                metadata: None,
             },
             Role::Tool => Self::Tool {
                 id: id.into(),
                 content: content.as_ref().to_string(),
                 tool_call_id: ToolCallId::random(),
                 error: None,
+                metadata: None,
+            },
+            Role::Other(role) => Self::Other {
+                role,
+                id: id.into(),
+                content: Some(content.as_ref().to_string()),
+                metadata: None,
+                extra: serde_json::Map::new(),
             },
         }
     }
@@ -314,6 +664,7 @@ impl Message {
             Message::Assistant { id, .. } => id,
             Message::User { id, .. } => id,
             Message::Tool { id, .. } => id,
+            Message::Other { id, .. } => id,
         }
     }
 
@@ -324,6 +675,7 @@ impl Message {
             Message::Assistant { id, .. } => id,
             Message::User { id, .. } => id,
             Message::Tool { id, .. } => id,
+            Message::Other { id, .. } => id,
         }
     }
 
@@ -334,6 +686,7 @@ impl Message {
             Message::Assistant { .. } => Role::Assistant,
             Message::User { .. } => Role::User,
             Message::Tool { .. } => Role::Tool,
+            Message::Other { role, .. } => Role::Other(role.clone()),
         }
     }
     pub fn content(&self) -> Option<&str> {
@@ -342,7 +695,9 @@ impl Message {
             Message::System { content, .. } => Some(content),
             Message::User { content, .. } => Some(content),
             Message::Tool { content, .. } => Some(content),
-            Message::Assistant { content, .. } => content.as_deref(),
+            Message::Assistant { content, .. } | Message::Other { content, .. } => {
+                content.as_deref()
+            }
         }
     }
 
@@ -352,7 +707,7 @@ impl Message {
             | Message::System { content, .. }
             | Message::User { content, .. }
             | Message::Tool { content, .. } => Some(content),
-            Message::Assistant { content, .. } => {
+            Message::Assistant { content, .. } | Message::Other { content, .. } => {
                 if content.is_none() {
                     *content = Some(String::new());
                 }
@@ -379,4 +734,32 @@ impl Message {
             _ => None,
         }
     }
+
+    pub fn metadata(&self) -> Option<&JsonValue> {
+        match self {
+            Message::Developer { metadata, .. }
+            | Message::System { metadata, .. }
+            | Message::Assistant { metadata, .. }
+            | Message::User { metadata, .. }
+            | Message::Tool { metadata, .. }
+            | Message::Other { metadata, .. } => metadata.as_ref(),
+        }
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut Option<JsonValue> {
+        match self {
+            Message::Developer { metadata, .. }
+            | Message::System { metadata, .. }
+            | Message::Assistant { metadata, .. }
+            | Message::User { metadata, .. }
+            | Message::Tool { metadata, .. }
+            | Message::Other { metadata, .. } => metadata,
+        }
+    }
+
+    /// Sets application-defined metadata, such as citations or UI hints
+    pub fn with_metadata(mut self, metadata: JsonValue) -> Self {
+        *self.metadata_mut() = Some(metadata);
+        self
+    }
 }
@@ -1,5 +1,6 @@
 use crate::types::ids::ToolCallId;
 use crate::types::message::FunctionCall;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 
@@ -19,6 +20,15 @@ impl ToolCall {
             function,
         }
     }
+
+    /// Deserializes the arguments of the call. Empty arguments, which some providers send for
+    /// functions without parameters, are read as an empty object.
+    pub fn parse_args<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        match self.function.arguments.trim() {
+            "" => serde_json::from_str("{}"),
+            arguments => serde_json::from_str(arguments),
+        }
+    }
 }
 
 /// A tool definition.
@@ -0,0 +1,14 @@
+//! Golden encodings of every event variant, including edge-case payloads such as unicode, large
+//! numbers, nulls and missing optional fields.
+//!
+//! `events.jsonl` holds one event per line exactly as it is serialized, and `events.sse` the same
+//! events framed as server-sent events, which the SSE parser of `ag-ui-client` decodes in its
+//! `tests/sse_test.rs`. Any change to these files is a change of the wire format.
+
+/// The events serialized as JSON, one per line
+pub const EVENTS_JSONL: &str = include_str!("events.jsonl");
+
+/// Returns the JSON encoding of each golden event
+pub fn events() -> impl Iterator<Item = &'static str> {
+    EVENTS_JSONL.lines()
+}
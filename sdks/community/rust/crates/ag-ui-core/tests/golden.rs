@@ -0,0 +1,30 @@
+mod fixtures;
+
+#[cfg(test)]
+mod tests {
+    use super::fixtures;
+    use ag_ui_core::event::{Event, EventType};
+
+    fn decode(json: &str) -> Event {
+        serde_json::from_str(json).unwrap_or_else(|err| panic!("Invalid fixture {json}: {err}"))
+    }
+
+    #[test]
+    fn test_golden_json_is_stable() {
+        for json in fixtures::events() {
+            let event = decode(json);
+            assert!(!event.is_unknown());
+            assert_eq!(serde_json::to_string(&event).unwrap(), json);
+        }
+    }
+
+    #[test]
+    fn test_golden_covers_every_event_type() {
+        let seen: Vec<EventType> = fixtures::events()
+            .map(|json| decode(json).event_type())
+            .collect();
+        for &event_type in EventType::ALL {
+            assert!(seen.contains(&event_type), "No fixture for {event_type:?}");
+        }
+    }
+}
@@ -1,6 +1,11 @@
 #[cfg(test)]
 mod tests {
     use ag_ui_core::error::AgUiError;
+    use ag_ui_core::event::{CustomEvent, Event, EventType};
+    use ag_ui_core::extensions::{Citation, CustomEventExtension, ToolResultDelta};
+    use ag_ui_core::history::reconcile_messages;
+    use ag_ui_core::llm;
+    use ag_ui_core::llm::anthropic::AnthropicToolUse;
     use ag_ui_core::types::{
         AssistantMessage, Context, DeveloperMessage, FunctionCall, Message, MessageId, Role,
         RunAgentInput, RunId, SystemMessage, ThreadId, Tool, ToolCall, ToolCallId, ToolMessage,
@@ -48,6 +53,7 @@ mod tests {
             id: MessageId::random(),
             content: "Hello".to_string(),
             name: None,
+            metadata: None,
         };
 
         let json = serde_json::to_string(&user_msg).unwrap();
@@ -172,6 +178,7 @@ mod tests {
                 content,
                 name,
                 tool_calls,
+                metadata,
             } => {
                 assert_eq!(id.to_string(), "00000000-0000-0000-0000-000000000000");
                 assert_eq!(
@@ -179,6 +186,7 @@ mod tests {
                     Some("I'll help you with that function.".to_string())
                 );
                 assert_eq!(name, Some("CodeHelper".to_string()));
+                assert!(metadata.is_none());
                 assert!(tool_calls.is_some());
                 let calls = tool_calls.unwrap();
                 assert_eq!(calls.len(), 1);
@@ -215,10 +223,16 @@ mod tests {
         assert_eq!(messages.len(), 3);
 
         match &messages[0] {
-            Message::User { id, content, name } => {
+            Message::User {
+                id,
+                content,
+                name,
+                metadata,
+            } => {
                 assert_eq!(id.to_string(), "00000000-0000-0000-0000-000000000000");
                 assert_eq!(content, "Hello!");
                 assert_eq!(*name, Some("Alice".to_string()));
+                assert!(metadata.is_none());
             }
             _ => panic!("Wrong message type"),
         }
@@ -317,4 +331,577 @@ mod tests {
             serde_json::from_str(json_str);
         assert!(wrong_input.is_err())
     }
+
+    #[test]
+    fn test_tool_result_delta_custom_event() {
+        let tool_call_id = ToolCallId::random();
+        let event: Event = ToolResultDelta::new(tool_call_id.clone(), "step 1/3")
+            .into_event()
+            .unwrap();
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "CUSTOM");
+        assert_eq!(json["name"], "TOOL_RESULT_DELTA");
+        assert_eq!(json["value"]["toolCallId"], json!(tool_call_id));
+        assert_eq!(json["value"]["delta"], "step 1/3");
+
+        let Event::Custom(custom) = serde_json::from_value::<Event>(json).unwrap() else {
+            panic!("expected a custom event");
+        };
+        let delta = ToolResultDelta::from_custom_event(&custom)
+            .unwrap()
+            .unwrap();
+        assert_eq!(delta.tool_call_id, tool_call_id);
+        assert_eq!(delta.delta, "step 1/3");
+
+        let other = CustomEvent::new("OTHER", json!({}));
+        assert!(ToolResultDelta::from_custom_event(&other).is_none());
+    }
+
+    #[test]
+    fn test_llm_tool_conversions() {
+        let tool = Tool::new(
+            "get_weather".to_string(),
+            "Get the weather for a city".to_string(),
+            json!({"type": "object", "properties": {"city": {"type": "string"}}}),
+        );
+
+        let openai = serde_json::to_value(llm::openai::tools(std::slice::from_ref(&tool))).unwrap();
+        assert_eq!(
+            openai,
+            json!([{
+                "type": "function",
+                "function": {
+                    "name": "get_weather",
+                    "description": "Get the weather for a city",
+                    "parameters": tool.parameters,
+                }
+            }])
+        );
+
+        let anthropic =
+            serde_json::to_value(llm::anthropic::tools(std::slice::from_ref(&tool))).unwrap();
+        assert_eq!(anthropic[0]["input_schema"], tool.parameters);
+
+        let tool_use: AnthropicToolUse = serde_json::from_value(json!({
+            "type": "tool_use",
+            "id": "toolu_01A",
+            "name": "get_weather",
+            "input": {"city": "Paris"}
+        }))
+        .unwrap();
+        let tool_call = ToolCall::from(tool_use);
+        assert_eq!(&*tool_call.id, "toolu_01A");
+        assert_eq!(tool_call.function.arguments, r#"{"city":"Paris"}"#);
+
+        let parent_id = MessageId::random();
+        let events: Vec<Event> = llm::tool_call_events(tool_call, Some(parent_id.clone()));
+        assert_eq!(events.len(), 3);
+        let Event::ToolCallStart(start) = &events[0] else {
+            panic!("expected a tool call start event");
+        };
+        assert_eq!(start.tool_call_name, "get_weather");
+        assert_eq!(start.parent_message_id, Some(parent_id));
+        assert!(matches!(&events[1], Event::ToolCallArgs(e) if e.delta == r#"{"city":"Paris"}"#));
+        assert!(matches!(&events[2], Event::ToolCallEnd(_)));
+    }
+
+    #[test]
+    fn test_message_metadata() {
+        let metadata = json!({"citations": [{"url": "https://example.com"}]});
+        let msg = Message::new_assistant("See the docs").with_metadata(metadata.clone());
+        assert_eq!(msg.metadata(), Some(&metadata));
+
+        let json = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["metadata"], metadata);
+        let deserialized: Message = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized, msg);
+
+        let json = serde_json::to_value(Message::new_user("Hello")).unwrap();
+        assert!(json.get("metadata").is_none());
+    }
+
+    #[test]
+    fn test_citation_resolution() {
+        let message_id = MessageId::random();
+        let citation = Citation::new(
+            message_id.clone(),
+            6..11,
+            json!({"url": "https://example.com"}),
+        );
+
+        // The range is not covered until the content reaches it
+        assert_eq!(citation.cited_text("Hello"), None);
+        assert_eq!(citation.cited_text("Héllo wor"), None);
+        assert_eq!(citation.cited_text("Héllo wörld!"), Some("wörld"));
+
+        let Event::Custom(custom) = citation.clone().into_event::<serde_json::Value>().unwrap()
+        else {
+            panic!("expected a custom event");
+        };
+        assert_eq!(custom.name, "CITATION");
+        assert_eq!(custom.value["start"], 6);
+        assert_eq!(
+            Citation::from_custom_event(&custom).unwrap().unwrap(),
+            citation
+        );
+    }
+
+    #[test]
+    fn test_canonical_hash() {
+        use ag_ui_core::canonical::{canonical_hash, canonical_json};
+
+        let a = json!({"b": [1.0, {"y": true, "x": null}], "a": "text", "timestamp": 1});
+        let b = json!({"a": "text", "b": [1, {"x": null, "y": true}], "timestamp": 2});
+        assert_eq!(
+            canonical_json(&a, &["timestamp"]).unwrap(),
+            r#"{"a":"text","b":[1,{"x":null,"y":true}]}"#
+        );
+        assert_eq!(canonical_hash(&a).unwrap(), canonical_hash(&b).unwrap());
+        assert_ne!(
+            canonical_hash(&a).unwrap(),
+            canonical_hash(&json!({"a": "other"})).unwrap()
+        );
+
+        // Inputs differing only in their run id hash identically
+        let input = |run_id: RunId| {
+            RunAgentInput::new(
+                ThreadId::from(Uuid::nil()),
+                run_id,
+                json!({"count": 1}),
+                vec![],
+                vec![],
+                vec![],
+                json!({}),
+            )
+        };
+        assert_eq!(
+            canonical_hash(&input(RunId::random())).unwrap(),
+            canonical_hash(&input(RunId::random())).unwrap()
+        );
+
+        // Excluded names only match envelope fields, not fields of the same name in the state
+        let snapshot = |timestamp: u32| json!({"type": "STATE_SNAPSHOT", "timestamp": 1, "snapshot": {"timestamp": timestamp}});
+        assert_ne!(
+            canonical_hash(&snapshot(1)).unwrap(),
+            canonical_hash(&snapshot(2)).unwrap()
+        );
+
+        // Nested fields are excluded by JSON pointer
+        assert_eq!(
+            canonical_json(&snapshot(1), &["/snapshot/timestamp", "/type"]).unwrap(),
+            r#"{"snapshot":{},"timestamp":1}"#
+        );
+    }
+
+    #[test]
+    fn test_unknown_event_compat() {
+        let payload = json!({"type": "SOME_FUTURE_EVENT", "timestamp": 3.0, "data": [1]});
+
+        // Deserialization is strict by default
+        assert!(serde_json::from_value::<Event>(payload.clone()).is_err());
+
+        let event: Event = Event::from_value_compat(payload.clone()).unwrap();
+        assert!(event.is_unknown());
+        assert_eq!(event.event_type(), EventType::Unknown);
+        assert_eq!(event.timestamp(), Some(3.0));
+        assert_eq!(serde_json::to_value(&event).unwrap(), payload);
+
+        // Known events are unaffected
+        let known = json!({"type": "STEP_STARTED", "stepName": "plan"});
+        let event: Event = Event::from_value_compat(known.clone()).unwrap();
+        assert!(matches!(event, Event::StepStarted(_)));
+        assert_eq!(serde_json::to_value(&event).unwrap(), known);
+        assert!(
+            Event::<serde_json::Value>::from_value_compat(json!({"type": "STEP_STARTED"})).is_err()
+        );
+    }
+
+    #[test]
+    fn test_custom_roles_round_trip() {
+        for role in ["function", "critic", "Tool", "user "] {
+            let parsed: Role = serde_json::from_value(json!(role)).unwrap();
+            assert_eq!(parsed, Role::Other(role.to_string()));
+            assert_eq!(serde_json::to_value(&parsed).unwrap(), json!(role));
+        }
+        assert_eq!(Role::from("tool"), Role::Tool);
+
+        // A custom role naming a protocol role creates a message that reads back the same
+        let message = Message::new(Role::Other("user".to_string()), Uuid::nil(), "Hi");
+        assert!(matches!(message, Message::User { .. }));
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(serde_json::from_value::<Message>(json).unwrap(), message);
+
+        let raw = json!({
+            "id": Uuid::nil(),
+            "role": "critic",
+            "content": "Needs more sources",
+            "score": 0.4,
+            "metadata": {"reviewer": "b"}
+        });
+        let message: Message = serde_json::from_value(raw.clone()).unwrap();
+        assert_eq!(message.role(), Role::Other("critic".to_string()));
+        assert_eq!(message.content(), Some("Needs more sources"));
+        assert_eq!(message.metadata(), Some(&json!({"reviewer": "b"})));
+        assert_eq!(serde_json::to_value(&message).unwrap(), raw);
+
+        // Content of another shape is kept
+        let raw = json!({"id": Uuid::nil(), "role": "function", "content": [{"type": "text"}]});
+        let message: Message = serde_json::from_value(raw.clone()).unwrap();
+        assert_eq!(message.content(), None);
+        assert_eq!(serde_json::to_value(&message).unwrap(), raw);
+
+        // So are null content and metadata
+        let raw = json!({"id": Uuid::nil(), "role": "critic", "content": null, "metadata": null});
+        let message: Message = serde_json::from_value(raw.clone()).unwrap();
+        assert_eq!(message.content(), None);
+        assert_eq!(serde_json::to_value(&message).unwrap(), raw);
+
+        // Fields may appear only once
+        for duplicated in ["content", "role", "score"] {
+            let json = format!(
+                r#"{{"id": "{}", "role": "critic", "score": 1, "content": "a", "{duplicated}": "b"}}"#,
+                Uuid::nil()
+            );
+            let err = serde_json::from_str::<Message>(&json).unwrap_err();
+            assert!(err.to_string().contains("duplicate field"), "{err}");
+        }
+        let json = format!(
+            r#"{{"id": "{}", "role": "user", "content": "a", "content": "b"}}"#,
+            Uuid::nil()
+        );
+        assert!(serde_json::from_str::<Message>(&json).is_err());
+
+        // Messages of a known role are still validated
+        let err = serde_json::from_value::<Message>(json!({"id": Uuid::nil(), "role": "user"}))
+            .unwrap_err();
+        assert!(err.to_string().contains("content"));
+
+        // Fields may precede the role, and errors keep their position
+        let message: Message = serde_json::from_str(&format!(
+            r#"{{"content": "Hi", "id": "{}", "role": "user"}}"#,
+            Uuid::nil()
+        ))
+        .unwrap();
+        assert_eq!(message.content(), Some("Hi"));
+        let err = serde_json::from_str::<Message>(&format!(
+            "{{\"id\": \"{}\",\n\"role\": \"user\",\n\"content\": 5}}",
+            Uuid::nil()
+        ))
+        .unwrap_err();
+        assert_eq!(err.line(), 3);
+    }
+
+    #[test]
+    fn test_reconcile_messages() {
+        let system = Message::new_system("Be brief");
+        let question = Message::new_user("What is AG-UI?");
+        let answer = Message::new_assistant("A protocol.");
+        let stored = vec![system.clone(), question.clone(), answer.clone()];
+
+        // The frontend resends the history with an edited question and a follow-up
+        let mut edited = question.clone();
+        *edited.content_mut().unwrap() = "What is AG-UI, briefly?".to_string();
+        let follow_up = Message::new_user("Thanks");
+        let incoming = vec![
+            system.clone(),
+            edited.clone(),
+            follow_up.clone(),
+            follow_up.clone(),
+        ];
+
+        let reconciled = reconcile_messages(&stored, &incoming);
+        assert!(reconciled.has_changes());
+        assert_eq!(
+            reconciled.messages,
+            vec![system, edited, answer, follow_up.clone()]
+        );
+        assert_eq!(reconciled.new, vec![follow_up.id().clone()]);
+        assert_eq!(reconciled.changed, vec![question.id().clone()]);
+
+        let unchanged = reconcile_messages(&reconciled.messages, &reconciled.messages);
+        assert!(!unchanged.has_changes());
+        assert_eq!(unchanged.messages, reconciled.messages);
+    }
+
+    #[test]
+    fn test_tool_call_parse_args() {
+        use ag_ui_core::partial_json::{PartialJson, parse_partial};
+        use serde_json::json;
+
+        #[derive(Debug, Default, PartialEq, serde::Deserialize)]
+        #[serde(default)]
+        struct Weather {
+            city: String,
+            days: Vec<u32>,
+        }
+
+        let tool_call = ToolCall::new(
+            ToolCallId::random(),
+            FunctionCall {
+                name: "get_weather".to_string(),
+                arguments: r#"{"city":"Paris","days":[1,2]}"#.to_string(),
+            },
+        );
+        let args: Weather = tool_call.parse_args().unwrap();
+        assert_eq!(args.city, "Paris");
+        let no_args = ToolCall::new(
+            ToolCallId::random(),
+            FunctionCall {
+                name: "now".to_string(),
+                arguments: String::new(),
+            },
+        );
+        assert_eq!(
+            no_args.parse_args::<serde_json::Value>().unwrap(),
+            json!({})
+        );
+
+        // Each prefix of a document completes to a best-effort value
+        let mut args = PartialJson::new();
+        let mut values = Vec::new();
+        for delta in [r#"{"ci"#, r#"ty":"Pa"#, r#"ris","da"#, r#"ys":[1,"#, "2]}"] {
+            args.push(delta);
+            values.push(args.value().unwrap());
+        }
+        assert_eq!(
+            values,
+            vec![
+                json!({}),
+                json!({"city": "Pa"}),
+                json!({"city": "Paris"}),
+                json!({"city": "Paris", "days": [1]}),
+                json!({"city": "Paris", "days": [1, 2]}),
+            ]
+        );
+        assert_eq!(
+            args.finish::<Weather>().unwrap(),
+            Weather {
+                city: "Paris".to_string(),
+                days: vec![1, 2],
+            }
+        );
+
+        let mut partial = PartialJson::new();
+        partial.push(r#"{"city":"Par"#);
+        assert_eq!(partial.parse::<Weather>().unwrap().city, "Par");
+        assert!(partial.finish::<Weather>().is_err());
+
+        // Truncated escapes and literals are dropped
+        assert_eq!(parse_partial(r#"{"a":"x\"#), Some(json!({"a": "x"})));
+        assert_eq!(parse_partial(r#"{"a":"\u00e"#), Some(json!({"a": ""})));
+        assert_eq!(parse_partial(r#"[true,fal"#), Some(json!([true])));
+        assert_eq!(parse_partial(r#"{"a":1,"b":"#), Some(json!({"a": 1})));
+        assert_eq!(parse_partial(r#"{"a":[1.5,-"#), Some(json!({"a": [1.5]})));
+        assert_eq!(parse_partial(r#"{"a":{"b":"#), Some(json!({"a": {}})));
+        assert_eq!(parse_partial(r#"{"a\"b":"c\"#), Some(json!({"a\"b": "c"})));
+        assert_eq!(parse_partial(r#"["{[\"]}", "#), Some(json!(["{[\"]}"])));
+
+        // Only objects and arrays are completed, if their structure is valid
+        assert_eq!(parse_partial(""), None);
+        assert_eq!(parse_partial("}"), None);
+        assert_eq!(parse_partial(r#""abc"#), None);
+        assert_eq!(parse_partial("12"), None);
+        assert_eq!(parse_partial(r#"{"a":1]"#), None);
+        assert_eq!(parse_partial(r#"{"a" "b"#), None);
+        assert_eq!(parse_partial(r#"[1,,"#), None);
+        assert_eq!(parse_partial(r#"{"a":1} {"#), None);
+
+        // A long truncated token is dropped at once, not one character at a time
+        let long = format!(r#"{{"a":[1],"{}"#, "b".repeat(100_000));
+        assert_eq!(parse_partial(&long), Some(json!({"a": [1]})));
+    }
+
+    #[test]
+    fn test_message_revision() {
+        use ag_ui_core::extensions::{CustomEventExtension, MessageRevision, TextEdit};
+
+        let message_id = MessageId::random();
+        let revision = MessageRevision::edit(
+            message_id.clone(),
+            vec![TextEdit::new(0..5, "Bonjour"), TextEdit::new(11..11, " 🌍")],
+        );
+        assert_eq!(
+            revision.apply("Hello, ünï").as_deref(),
+            Some("Bonjour, ün 🌍ï")
+        );
+        assert_eq!(revision.apply("Hi"), None);
+        assert_eq!(
+            MessageRevision::replace(message_id.clone(), "Bye").apply("Hello"),
+            Some("Bye".to_string())
+        );
+
+        let event = revision.clone().into_custom_event().unwrap();
+        assert_eq!(event.name, "MESSAGE_REVISION");
+        assert_eq!(
+            event.value,
+            serde_json::json!({
+                "messageId": message_id,
+                "edits": [
+                    {"start": 0, "end": 5, "text": "Bonjour"},
+                    {"start": 11, "end": 11, "text": " 🌍"},
+                ],
+            })
+        );
+        assert_eq!(
+            MessageRevision::from_custom_event(&event).unwrap().unwrap(),
+            revision
+        );
+    }
+
+    #[test]
+    fn test_message_revision_ranges() {
+        use ag_ui_core::extensions::{MessageRevision, TextEdit};
+
+        let message_id = MessageId::random();
+        let revision = MessageRevision::edit(
+            message_id.clone(),
+            vec![TextEdit::new(2..4, "🌍🌍🌍"), TextEdit::new(10..10, "!")],
+        );
+        // Before, after and across the edits
+        assert_eq!(revision.revise_range(0..2), Some(0..2));
+        assert_eq!(revision.revise_range(5..8), Some(6..9));
+        assert_eq!(revision.revise_range(8..12), None);
+        assert_eq!(revision.revise_range(3..6), None);
+        assert_eq!(
+            MessageRevision::replace(message_id, "Bye").revise_range(0..2),
+            None
+        );
+    }
+
+    #[test]
+    fn test_metrics() {
+        use ag_ui_core::extensions::{CustomEventExtension, Metrics, RunMetrics};
+
+        let metrics = Metrics::new("gpt-4o")
+            .with_tokens(120, 30)
+            .with_latency_ms(850);
+        let event = metrics.clone().into_custom_event().unwrap();
+        assert_eq!(event.name, "METRICS");
+        assert_eq!(
+            event.value,
+            serde_json::json!({
+                "model": "gpt-4o",
+                "inputTokens": 120,
+                "outputTokens": 30,
+                "latencyMs": 850,
+            })
+        );
+        assert_eq!(
+            Metrics::from_custom_event(&event).unwrap().unwrap(),
+            metrics
+        );
+
+        let mut run = RunMetrics::default();
+        for metrics in [
+            &metrics,
+            &Metrics::new("gpt-4o").with_tokens(200, 50),
+            &Metrics::new("claude").with_latency_ms(100),
+            &Metrics::default(),
+        ] {
+            run.record(metrics);
+        }
+        assert_eq!(run.calls, 4);
+        assert_eq!(run.total_tokens(), 400);
+        assert_eq!(run.latency_ms, 950);
+        assert_eq!(run.models, vec!["gpt-4o".to_string(), "claude".to_string()]);
+    }
+
+    #[test]
+    fn test_capabilities() {
+        use ag_ui_core::event::EventType;
+        use ag_ui_core::extensions::{CustomEventExtension, MessagesDelta, Retry};
+        use ag_ui_core::protocol::CAPABILITIES;
+
+        assert!(CAPABILITIES.supports_event_type(EventType::ToolCallChunk));
+        assert!(!CAPABILITIES.supports_event_type(EventType::Unknown));
+        assert!(!EventType::ALL.contains(&EventType::Unknown));
+        assert!(CAPABILITIES.supports_custom_event(Retry::NAME));
+        assert!(CAPABILITIES.supports_custom_event(MessagesDelta::NAME));
+        assert!(!CAPABILITIES.supports_custom_event("UNHEARD_OF"));
+        assert!(CAPABILITIES.supports_content_type("text/event-stream"));
+
+        let json = serde_json::to_value(CAPABILITIES).unwrap();
+        assert_eq!(json["eventTypes"][0], "TEXT_MESSAGE_START");
+        assert_eq!(
+            json["eventTypes"].as_array().unwrap().len(),
+            CAPABILITIES.event_types.len()
+        );
+        assert_eq!(
+            json["contentTypes"],
+            serde_json::json!(["text/event-stream"])
+        );
+    }
+
+    #[test]
+    fn test_state_patcher() {
+        use ag_ui_core::{AgentState, Patch, StatePatcher};
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+        struct Plan {
+            steps: Vec<String>,
+            done: bool,
+        }
+        impl AgentState for Plan {}
+
+        let patch = |json: serde_json::Value| -> Patch { serde_json::from_value(json).unwrap() };
+        let mut patcher = StatePatcher::new();
+        let mut plan = Plan::default();
+
+        patcher
+            .apply(
+                &mut plan,
+                &patch(serde_json::json!([{"op": "add", "path": "/steps/-", "value": "plan"}])),
+            )
+            .unwrap();
+        patcher
+            .apply(
+                &mut plan,
+                &patch(serde_json::json!([{"op": "replace", "path": "/done", "value": true}])),
+            )
+            .unwrap();
+        assert_eq!(
+            plan,
+            Plan {
+                steps: vec!["plan".to_string()],
+                done: true,
+            }
+        );
+
+        // A patch producing an invalid state is rejected, and the next patch starts over
+        let invalid =
+            patch(serde_json::json!([{"op": "replace", "path": "/done", "value": "yes"}]));
+        assert!(patcher.apply(&mut plan, &invalid).is_err());
+        let failing = patch(serde_json::json!([{"op": "remove", "path": "/missing"}]));
+        assert!(patcher.apply(&mut plan, &failing).is_err());
+        assert!(plan.done);
+
+        patcher.reset();
+        let mut plan = Plan::default();
+        patcher
+            .apply(
+                &mut plan,
+                &patch(serde_json::json!([{"op": "replace", "path": "/done", "value": true}])),
+            )
+            .unwrap();
+        assert_eq!(
+            plan,
+            Plan {
+                steps: vec![],
+                done: true
+            }
+        );
+
+        // JSON states are patched in place, and left as they were on failure
+        let mut state = serde_json::json!({"count": 1});
+        patcher
+            .apply(
+                &mut state,
+                &patch(serde_json::json!([{"op": "replace", "path": "/count", "value": 2}])),
+            )
+            .unwrap();
+        assert!(patcher.apply(&mut state, &failing).is_err());
+        assert_eq!(state, serde_json::json!({"count": 2}));
+    }
 }